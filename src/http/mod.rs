@@ -1,2 +1,16 @@
 mod parsers;
-pub use self::parsers::parse_heads;
+pub use self::parsers::{parse_heads, HeadError};
+
+/// A response to a request whose head could not be read
+/// In debug builds the body shows where the head is invalid.
+pub fn bad_request(error: &HeadError) -> String {
+    let body = match (cfg!(debug_assertions), &error.diagnostic) {
+        (true, Some(diagnostic)) => format!("{}\n\n{diagnostic}\n", error.message),
+        (true, None) => format!("{}\n", error.message),
+        (false, _) => "Bad Request\n".to_string(),
+    };
+    format!(
+        "HTTP/1.1 400 Bad Request\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
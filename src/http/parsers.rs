@@ -1,177 +1,415 @@
-use nom::error::Error;
 use std::collections::HashMap;
 use std::io::Read;
 use std::net::TcpStream;
 
 use super::super::Request;
+use crate::parse::diagnostic::Diagnostic;
+use crate::parse::stream::Stream;
+use crate::parse::Parse;
 
-pub fn parse_heads(stream: &mut TcpStream) -> Result<Request, &str> {
-    let mut acc = Vec::<u8>::new();
-    const BUFFER_SIZE: usize = 1;
-    let mut buffer = [0u8; BUFFER_SIZE];
+const BUFFER_SIZE: usize = 1024;
 
-    // Parsing the GET / HTTP/1.1
-    let parsed_start_line: Result<(&[u8], (String, String, String)), nom::Err<Error<&[u8]>>> = loop {
-        match stream.read(&mut buffer) {
-            Ok(read_bytes) => {
-                let actual_read = &buffer[..read_bytes];
-                if acc.len() == 0 {
-                    match http_parsers::parse_start_line(actual_read) {
-                        Err(nom::Err::Incomplete(_)) => {
-                            if read_bytes < BUFFER_SIZE {
-                                return Err("The request is incomplete");
-                            }
-                            acc.extend_from_slice(actual_read)
-                        }
-                        any @ _ => break any,
-                    }
-                } else {
-                    acc.extend_from_slice(actual_read);
-                    match http_parsers::parse_start_line(&acc) {
-                        Err(nom::Err::Incomplete(_)) => {
-                            if read_bytes < BUFFER_SIZE {
-                                return Err("The request is incomplete");
-                            }
-                        }
-                        any @ _ => break any,
-                    }
-                }
-            }
-            e @ Err(_) => {
-                println!("Socket read error {:?}", e);
-                return Err("Socket read error");
-            }
-        }
-    };
+/// Most bytes read for a request head, like the 8 KiB that common servers accept
+const MAX_HEAD: usize = 8 * 1024;
 
-    let mut acc: Vec<u8>;
+/// Why a request head could not be read
+#[derive(Debug)]
+pub struct HeadError {
+    pub message: &'static str,
+    /// Where the head is invalid, when it could not be parsed
+    pub diagnostic: Option<String>,
+}
 
-    let start_line = match parsed_start_line {
-        Ok((surplus, parsed_http_title)) => {
-            acc = surplus.to_vec();
-            parsed_http_title
+impl From<&'static str> for HeadError {
+    fn from(message: &'static str) -> Self {
+        HeadError {
+            message,
+            diagnostic: None,
         }
-        _ => return Err("The HTTP title could not be parsed"),
-    };
+    }
+}
+
+pub fn parse_heads(stream: &mut TcpStream) -> Result<Request<'_>, HeadError> {
+    let mut input = Stream::new();
+    let mut head = Vec::new();
 
-    // Parsing headers
+    // Parsing the GET / HTTP/1.1
+    let (method, target, version) = read_parse(
+        stream,
+        &mut input,
+        &mut head,
+        http_parsers::parse_start_line,
+        "The HTTP title could not be parsed",
+    )?;
+
+    // Parsing headers, until the header/body divider \r\n
     let mut headers = HashMap::<String, String>::new();
-    let mut buffer = [0u8; BUFFER_SIZE];
+    for (key, value) in read_parse(
+        stream,
+        &mut input,
+        &mut head,
+        http_parsers::parse_headers,
+        "Could not parse the headers, or there are too many",
+    )? {
+        if headers.insert(key, value).is_some() {
+            return Err("Identical keys in header".into());
+        }
+    }
+
+    Ok(Request {
+        method,
+        target,
+        version,
+        headers,
+        read_body: input.into_buffered(),
+        unread_stream: stream,
+    })
+}
 
+/// Parses the next output from the input, and reads from the TCP stream whenever the parser needs more
+/// No more is read once `MAX_HEAD` bytes have been, so at most `MAX_HEAD + BUFFER_SIZE` are.
+/// In debug builds the bytes read are kept in `head`, to show where the head is invalid.
+fn read_parse<R, O, P>(
+    stream: &mut R,
+    input: &mut Stream,
+    head: &mut Vec<u8>,
+    parser: P,
+    error: &'static str,
+) -> Result<O, HeadError>
+where
+    R: Read,
+    P: for<'a> Fn(&'a [u8]) -> Parse<&'a [u8], O>,
+{
+    let mut buffer = [0u8; BUFFER_SIZE];
     loop {
-        let mut done_with_headers = false;
-        loop {
-            match http_parsers::parse_header(&acc) {
-                Ok((surplus, (key, value))) => {
-                    if let Some(_) = headers.insert(key.to_string(), value.to_string()) {
-                        return Err("Identical keys in header");
+        match input.parse(&parser) {
+            Ok(Some(output)) => return Ok(output),
+            Ok(None) if input.fed() >= MAX_HEAD => {
+                return Err("The request head is too large".into())
+            }
+            Ok(None) => match stream.read(&mut buffer) {
+                Ok(0) => return Err("The request is incomplete".into()),
+                Ok(read_bytes) => {
+                    input.feed(&buffer[..read_bytes]);
+                    if cfg!(debug_assertions) {
+                        head.extend_from_slice(&buffer[..read_bytes]);
                     }
-                    acc = surplus.to_vec();
                 }
-                Err(nom::Err::Incomplete(_)) => break,
-                Err(nom::Err::Error(Error { input, code }))
-                    if code == nom::error::ErrorKind::IsNot && input[0] == 13 =>
-                {
-                    done_with_headers = true;
-                    break;
+                e @ Err(_) => {
+                    println!("Socket read error {:?}", e);
+                    return Err("Socket read error".into());
                 }
-                _ => return Err("Could not parse a spesific header"),
-            }
-        }
-        if done_with_headers {
-            break;
-        }
-        match stream.read(&mut buffer) {
-            Ok(read_bytes) => {
-                let actual_read = &buffer[..read_bytes];
-                acc.extend_from_slice(actual_read);
-            }
-            e @ Err(_) => {
-                println!("Socket read error {:?}", e);
-                return Err("Socket read error");
+            },
+            // The error is relative to the end of the buffered bytes, which is the end of `head`
+            Err(e) => {
+                return Err(HeadError {
+                    message: error,
+                    diagnostic: cfg!(debug_assertions)
+                        .then(|| Diagnostic::new(&head.as_slice(), &e).to_string()),
+                })
             }
         }
     }
+}
 
-    // Parsing header/body divider \r\n
+mod http_parsers {
+    use std::str::{from_utf8, Utf8Error};
 
-    while acc.len() < 2 {
-        match stream.read(&mut buffer) {
-            Ok(read_bytes) => {
-                let actual_read = &buffer[..read_bytes];
-                acc.extend_from_slice(actual_read);
-            }
-            e @ Err(_) => {
-                println!("Socket read error {:?}", e);
-                return Err("Socket read error");
-            }
-        }
+    use crate::parse::class::CharClass;
+    use crate::parse::comb::{map, map_result, not};
+    use crate::parse::repeat::repeat_range;
+    use crate::parse::sequence::{around, preceded, terminated};
+    use crate::parse::str::{char, literal, other_than, take_class};
+    use crate::parse::tools::{halt, label};
+    use crate::parse::Parse;
+    use crate::serial;
+
+    /// Most header lines in a request head
+    const MAX_HEADERS: usize = 100;
+
+    fn space(i: &[u8]) -> Parse<&[u8], u8> {
+        char(b' ')(i)
     }
-    match http_parsers::parse_divider(&acc) {
-        Ok((surplus, _)) => acc = surplus.to_vec(),
-        _ => return Err("Could not read divider (last \r\n)"),
+
+    fn text(i: &[u8]) -> Result<String, Utf8Error> {
+        Ok(from_utf8(i)?.to_owned())
     }
 
-    Ok({
-        let (method, target, version) = start_line;
-        Request {
-            method,
-            target,
-            version,
-            headers,
-            read_body: acc,
-            unread_stream: stream,
-        }
-    })
-}
+    /// Characters of tokens, like methods and header names
+    const TOKEN: CharClass = CharClass::ascii("!#$%&'*+-.^_`|~")
+        .union(CharClass::range('0', '9'))
+        .union(CharClass::range('a', 'z'))
+        .union(CharClass::range('A', 'Z'))
+        .named("token");
 
-mod http_parsers {
-    use nom::{
-        bytes::streaming::{is_not, tag},
-        character::streaming::alpha1,
-        combinator::{map, map_res},
-        sequence::{preceded, separated_pair, terminated, tuple},
-        IResult,
-    };
-    use std::str::from_utf8;
-
-    fn space(i: &[u8]) -> IResult<&[u8], &[u8]> {
-        tag(b" ")(i)
+    /// Any token is a method, which is given in lowercase, since the methods of RFC 9110 are
+    /// matched ignoring case
+    fn method(i: &[u8]) -> Parse<&[u8], String> {
+        label(
+            map_result(
+                take_class(&TOKEN),
+                |m| text(m).map(|m| m.to_ascii_lowercase()),
+                "method",
+            ),
+            "method",
+        )(i)
     }
 
-    fn letters(i: &[u8]) -> IResult<&[u8], &str> {
-        map_res(alpha1, |s| std::str::from_utf8(s))(i)
-    }
-    pub fn url(i: &[u8]) -> IResult<&[u8], &str> {
-        map_res(is_not(" "), |s| std::str::from_utf8(s))(i)
+    pub fn url(i: &[u8]) -> Parse<&[u8], String> {
+        map_result(other_than(" "), text, "target")(i)
     }
 
-    pub fn parse_start_line(i: &[u8]) -> IResult<&[u8], (String, String, String)> {
-        tuple((
-            map(terminated(letters, space), |s| s.to_lowercase()),
-            map(terminated(url, space), |s| s.to_owned()),
-            map(
-                map_res(
-                    terminated(preceded(tag("HTTP/"), is_not("\r")), tag("\r\n")),
-                    |s| std::str::from_utf8(s),
+    pub fn parse_start_line(i: &[u8]) -> Parse<&[u8], (String, String, String)> {
+        serial!(
+            terminated(method, space),
+            terminated(url, space),
+            terminated(
+                preceded(
+                    literal("HTTP/"),
+                    map_result(other_than("\r"), text, "version"),
                 ),
-                |s| s.to_owned(),
+                literal("\r\n"),
             ),
-        ))(i)
+        )(i)
     }
-    pub fn parse_header(i: &[u8]) -> IResult<&[u8], (String, &str)> {
+
+    pub fn parse_header(i: &[u8]) -> Parse<&[u8], (String, String)> {
         terminated(
-            separated_pair(
-                map(map_res(is_not(":\r"), |s| from_utf8(s)), |s| {
+            around(
+                map(map_result(other_than(":\r"), text, "header name"), |s| {
                     s.to_lowercase()
                 }),
-                tag(": "),
-                map_res(is_not("\r"), |s| from_utf8(s)),
+                literal(": "),
+                map_result(other_than("\r"), text, "header value"),
+            ),
+            literal("\r\n"),
+        )(i)
+    }
+
+    /// The header lines, up to the empty line dividing the head from the body
+    /// A line that isn't empty must be a header, so the error is where the header is invalid.
+    pub fn parse_headers(i: &[u8]) -> Parse<&[u8], Vec<(String, String)>> {
+        terminated(
+            repeat_range(
+                0,
+                MAX_HEADERS,
+                preceded(not(literal("\r\n")), halt(parse_header)),
             ),
-            tag("\r\n"),
+            literal("\r\n"),
         )(i)
     }
-    pub fn parse_divider(i: &[u8]) -> IResult<&[u8], &[u8]> {
-        tag(b"\r\n")(i)
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::read_parse;
+        use super::*;
+        use crate::parse::abnf::Grammar;
+        use crate::parse::error::Error;
+        use crate::parse::generate::{Generator, Rng};
+        use crate::parse::stream::Stream;
+
+        #[test]
+        fn test_start_line() {
+            assert_eq!(
+                parse_start_line(b"GET /index.html HTTP/1.1\r\nHost"),
+                Parse::Success(
+                    (
+                        "get".to_string(),
+                        "/index.html".to_string(),
+                        "1.1".to_string()
+                    ),
+                    &b"Host"[..]
+                )
+            );
+            assert!(parse_start_line(b"GET /index.html HTTP/1").is_limit());
+            assert!(parse_start_line(b"GET  HTTP/1.1\r\n").is_retreat());
+            assert_eq!(
+                parse_start_line(b"Post / HTTP/1.1\r\n"),
+                Parse::Success(
+                    ("post".to_string(), "/".to_string(), "1.1".to_string()),
+                    &b""[..]
+                )
+            );
+            // Unknown methods are lowercase like the known ones
+            assert_eq!(
+                parse_start_line(b"BREW / HTTP/1.1\r\n"),
+                Parse::Success(
+                    ("brew".to_string(), "/".to_string(), "1.1".to_string()),
+                    &b""[..]
+                )
+            );
+            assert!(parse_start_line(b"GET(/) / HTTP/1.1\r\n").is_retreat());
+        }
+
+        #[test]
+        fn test_headers() {
+            let mut stream = Stream::new();
+            let head = b"Host: example.com\r\nAccept: */*\r\n\r\nbody";
+            let mut headers = None;
+            for byte in head.chunks(1) {
+                stream.feed(byte);
+                if headers.is_none() {
+                    headers = stream.parse(parse_headers).unwrap();
+                }
+            }
+            assert_eq!(
+                headers.unwrap(),
+                vec![
+                    ("host".to_string(), "example.com".to_string()),
+                    ("accept".to_string(), "*/*".to_string()),
+                ]
+            );
+            assert_eq!(stream.buffered(), b"body");
+
+            let head = "A: b\r\n".repeat(MAX_HEADERS) + "\r\n";
+            assert!(parse_headers(head.as_bytes()).finish().is_ok());
+            let head = "A: b\r\n".repeat(MAX_HEADERS + 1) + "\r\n";
+            assert!(parse_headers(head.as_bytes()).is_halt());
+        }
+
+        #[test]
+        #[cfg_attr(
+            not(debug_assertions),
+            ignore = "diagnostics are only made in debug builds"
+        )]
+        fn test_diagnostic() {
+            let mut request: &[u8] = b"GET / HTTP/1.1\r\nHost example.com\r\n\r\n";
+            let (mut input, mut head) = (Stream::new(), Vec::new());
+            read_parse(
+                &mut request,
+                &mut input,
+                &mut head,
+                parse_start_line,
+                "start",
+            )
+            .unwrap();
+            let error = read_parse(&mut request, &mut input, &mut head, parse_headers, "header")
+                .unwrap_err();
+            assert_eq!(error.message, "header");
+            assert_eq!(
+                error.diagnostic.unwrap(),
+                "\
+error: expected \": \"
+ --> line 2, column 17
+  |
+2 | Host example.com
+  |                 ^"
+            );
+        }
+
+        /// Request heads of RFC 9112, with no obsolete text
+        /// The known methods are an alternative of their own, so they are generated as well.
+        const HEAD: &str = "\
+head           = start-line *( field-line CRLF ) CRLF
+start-line     = method SP request-target SP HTTP-version CRLF
+method         = known-method / token
+known-method   = \"GET\" / \"HEAD\" / \"POST\" / \"PUT\" / \"DELETE\" / \"CONNECT\"
+               / \"OPTIONS\" / \"TRACE\" / \"PATCH\"
+token          = 1*tchar
+request-target = 1*VCHAR
+HTTP-version   = %s\"HTTP/\" DIGIT \".\" DIGIT
+field-line     = field-name \": \" field-value
+field-name     = 1*tchar
+tchar          = \"!\" / \"#\" / \"$\" / \"%\" / \"&\" / \"'\" / \"*\" / \"+\" / \"-\" / \".\"
+               / \"^\" / \"_\" / \"`\" / \"|\" / \"~\" / DIGIT / ALPHA
+field-value    = VCHAR *( *SP VCHAR )
+";
+
+        /// The bytes split in chunks of random sizes, up to 16 bytes
+        fn split<'a>(rng: &mut Rng, mut bytes: &'a [u8]) -> Vec<&'a [u8]> {
+            let mut chunks = Vec::new();
+            while !bytes.is_empty() {
+                let (chunk, rest) = bytes.split_at(1 + rng.below(bytes.len().min(16)));
+                chunks.push(chunk);
+                bytes = rest;
+            }
+            chunks
+        }
+
+        type Head = ((String, String, String), Vec<(String, String)>);
+
+        /// Parses a head fed in chunks, like `parse_heads` reads it from the socket
+        fn parse_chunks(chunks: &[&[u8]]) -> Result<Option<Head>, Error> {
+            let mut stream = Stream::new();
+            let mut start_line = None;
+            for chunk in chunks.iter().map(Some).chain([None]) {
+                match chunk {
+                    Some(chunk) => stream.feed(chunk),
+                    None => stream.end(),
+                }
+                if start_line.is_none() {
+                    start_line = stream.parse(parse_start_line)?;
+                }
+                let Some(start_line) = &start_line else {
+                    continue;
+                };
+                if let Some(headers) = stream.parse(parse_headers)? {
+                    return Ok(Some((start_line.clone(), headers)));
+                }
+            }
+            Ok(None)
+        }
+
+        #[test]
+        fn test_generated() {
+            let grammar: Grammar = HEAD.parse().unwrap();
+            let mut generator = Generator::new(&grammar, 9112).max_repeat(6);
+            for _ in 0..200 {
+                let input = generator.generate("head");
+                let tree = grammar.parser("head")(&input).finish().unwrap().0;
+                let text = |rule| tree.find(rule).unwrap().text();
+                let chunks = split(generator.rng(), input.as_bytes());
+                let ((method, target, version), lines) = parse_chunks(&chunks).unwrap().unwrap();
+                assert_eq!(method, text("method").to_lowercase(), "{input}");
+                assert_eq!(target, text("request-target"), "{input}");
+                assert_eq!(version, text("HTTP-version")["HTTP/".len()..], "{input}");
+                let headers: Vec<_> = tree
+                    .find_all("field-line")
+                    .iter()
+                    .map(|line| {
+                        let name = line.find("field-name").unwrap().text().to_lowercase();
+                        let value = line.find("field-value").unwrap().text().to_string();
+                        (name, value)
+                    })
+                    .collect();
+                assert_eq!(lines, headers, "{input}");
+
+                // Invalid heads must not panic
+                let mutated = generator.mutate(&input);
+                let _ = parse_chunks(&split(generator.rng(), mutated.as_bytes()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_parse() {
+        let read = |mut request: &[u8]| {
+            let mut head = Vec::new();
+            let result = read_parse(
+                &mut request,
+                &mut Stream::new(),
+                &mut head,
+                http_parsers::parse_start_line,
+                "The HTTP title could not be parsed",
+            );
+            (result.map_err(|e| e.message), head.len())
+        };
+        let (result, _) = read(b"GET / HTTP/1.1\r\n");
+        assert_eq!(
+            result,
+            Ok(("get".to_string(), "/".to_string(), "1.1".to_string()))
+        );
+        assert_eq!(read(b"GET / HTTP/1.1").0, Err("The request is incomplete"));
+
+        // A head that doesn't end is read up to the limit
+        let target = "a".repeat(100 * MAX_HEAD);
+        let (result, kept) = read(format!("GET /{target} HTTP/1.1\r\n").as_bytes());
+        assert_eq!(result, Err("The request head is too large"));
+        assert!(kept <= MAX_HEAD + BUFFER_SIZE);
     }
 }
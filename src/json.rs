@@ -1,17 +1,39 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt::Write;
+use std::hash::Hash;
+use std::num::ParseFloatError;
+use std::str::FromStr;
 
-use crate::parse::comb::either_of;
-use crate::parse::comb::{either, map, map_result, optional};
-use crate::parse::repeat::repeat_any;
-use crate::parse::repeat::separated_items;
-use crate::parse::sequence::{around, between, preceded, serial, serial3, serial4};
+use crate::parse::class::CharClass;
+use crate::parse::comb::{either, map, map_bool, map_option, map_result, peek, position};
+use crate::parse::error::{Error, Expected};
+use crate::parse::escape::escaped_transform;
+use crate::parse::parser::Parser;
+use crate::parse::recover::{recover_with, with_recovery};
+use crate::parse::repeat::Trailing;
+use crate::parse::sequence::{between, preceded, serial, terminated};
+use crate::parse::state::{self, lift};
 use crate::parse::str::{
-    char, char_of, literal, other_than, pop, take, take_some_while, take_while,
+    char, char_of, literal, pop, take, take_class, take_some_while, take_while,
 };
-use crate::parse::tools::halt;
+use crate::parse::syntax::{leaf, Syntax};
+use crate::parse::tools::{accept_limit, halt, label};
+use crate::parse::trace::trace;
 use crate::parse::Parse;
+use crate::serial;
 
-#[derive(Debug, PartialEq)]
+/// Characters of a string that aren't escaped
+const UNESCAPED: CharClass = CharClass::ascii("\"\\").negate();
+
+/// Most items in an array or members in an object
+const MAX_MEMBERS: usize = 1 << 16;
+
+/// Default limit on how deeply arrays and objects nest
+/// Each level takes about 20 KB of stack in debug builds, so this fits in the 2 MiB of a thread.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum JSON {
     UnsignedInt(usize),
     SignedInt(isize),
@@ -23,9 +45,187 @@ pub enum JSON {
     Array(Vec<JSON>),
 }
 
+/// A JSON value that borrows its strings and keys from the input where they have no escapes
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonRef<'a> {
+    UnsignedInt(usize),
+    SignedInt(isize),
+    Float(f64),
+    String(Cow<'a, str>),
+    Bool(bool),
+    Null,
+    Object(HashMap<Cow<'a, str>, JsonRef<'a>>),
+    Array(Vec<JsonRef<'a>>),
+}
+
+/// Limits and diagnostics for parsing a document
+#[derive(Debug, Clone)]
+pub struct Context {
+    max_depth: usize,
+    depth: usize,
+    diagnostics: Vec<Error>,
+}
+
+impl Context {
+    /// Arrays and objects may nest at most `max_depth` levels deep
+    pub fn new(max_depth: usize) -> Self {
+        Context {
+            max_depth,
+            depth: 0,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Problems found in a valid document, like duplicate object keys
+    pub fn diagnostics(&self) -> &[Error] {
+        &self.diagnostics
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context::new(MAX_DEPTH)
+    }
+}
+
 impl JSON {
     fn parse(input: &str) -> Parse<&str, JSON> {
-        either(object, array)(input)
+        state::either(object, array)(&mut Context::default(), input)
+    }
+
+    /// Parses a complete JSON document within the limits of `context`, which collects diagnostics
+    pub fn parse_with(input: &str, context: &mut Context) -> Result<JSON, Error> {
+        document(context, input)
+    }
+
+    /// Parses a JSON document, recovering from invalid values
+    /// Invalid array items and object values are replaced with `null`, anything between a value and
+    /// the next comma or closing bracket is skipped, and every error is returned.
+    /// The document is `None` if it could not be recovered.
+    pub fn parse_partial(input: &str) -> (Option<JSON>, Vec<Error>) {
+        let (result, mut errors) = with_recovery(|| document(&mut Context::default(), input));
+        match result {
+            Ok(json) => (Some(json), errors),
+            Err(e) => {
+                errors.push(e);
+                (None, errors)
+            }
+        }
+    }
+
+    /// The syntax of a JSON value after whitespace, which prints without whitespace
+    /// It parses with the rules of a document, within the default `Context`. The whitespace after
+    /// the value is left, since a number at the end of the input would otherwise be undecided.
+    pub fn syntax() -> impl Syntax<JSON> {
+        leaf(
+            |i: &str| state::preceded(lift(whitespace), element)(&mut Context::default(), i),
+            |json: &JSON, out: &mut String| print_value(json, 0, out),
+        )
+    }
+
+    /// The value as a JSON document, with the keys of objects in order
+    /// `None` if it has a float that JSON can't represent, like infinity, or nests deeper than
+    /// a document may.
+    pub fn to_text(&self) -> Option<String> {
+        JSON::syntax().to_text(self)
+    }
+}
+
+impl FromStr for JSON {
+    type Err = Error;
+
+    /// Parses a complete JSON document
+    /// Use `Error::offset` with the input to find where the document is invalid
+    fn from_str(input: &str) -> Result<Self, Error> {
+        JSON::parse_with(input, &mut Context::default())
+    }
+}
+
+impl<'a> JsonRef<'a> {
+    /// Parses a complete JSON document within the limits of `context`, which collects diagnostics
+    pub fn parse_with(input: &'a str, context: &mut Context) -> Result<JsonRef<'a>, Error> {
+        document(context, input)
+    }
+
+    /// Copies the borrowed strings and keys
+    pub fn into_owned(self) -> JSON {
+        match self {
+            JsonRef::UnsignedInt(n) => JSON::UnsignedInt(n),
+            JsonRef::SignedInt(n) => JSON::SignedInt(n),
+            JsonRef::Float(n) => JSON::Float(n),
+            JsonRef::String(s) => JSON::String(s.into_owned()),
+            JsonRef::Bool(b) => JSON::Bool(b),
+            JsonRef::Null => JSON::Null,
+            JsonRef::Object(members) => JSON::Object(
+                members
+                    .into_iter()
+                    .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                    .collect(),
+            ),
+            JsonRef::Array(items) => {
+                JSON::Array(items.into_iter().map(JsonRef::into_owned).collect())
+            }
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a str> for JsonRef<'a> {
+    type Error = Error;
+
+    /// Parses a complete JSON document, borrowing from the input
+    fn try_from(input: &'a str) -> Result<Self, Error> {
+        document(&mut Context::default(), input)
+    }
+}
+
+/// The values documents are parsed into, which are `JSON` and `JsonRef`
+/// Values are built as they are parsed, so a `JSON` copies each string once.
+trait Build<'a>: Clone {
+    type Key: Eq + Hash + From<Cow<'a, str>>;
+
+    /// A value that isn't an array or an object
+    fn scalar(value: JsonRef<'a>) -> Self;
+    fn array(items: Vec<Self>) -> Self;
+    fn object(members: HashMap<Self::Key, Self>) -> Self;
+}
+
+impl<'a> Build<'a> for JsonRef<'a> {
+    type Key = Cow<'a, str>;
+
+    fn scalar(value: JsonRef<'a>) -> Self {
+        value
+    }
+    fn array(items: Vec<Self>) -> Self {
+        JsonRef::Array(items)
+    }
+    fn object(members: HashMap<Self::Key, Self>) -> Self {
+        JsonRef::Object(members)
+    }
+}
+
+impl<'a> Build<'a> for JSON {
+    type Key = String;
+
+    fn scalar(value: JsonRef<'a>) -> Self {
+        value.into_owned()
+    }
+    fn array(items: Vec<Self>) -> Self {
+        JSON::Array(items)
+    }
+    fn object(members: HashMap<Self::Key, Self>) -> Self {
+        JSON::Object(members)
+    }
+}
+
+fn document<'a, V: Build<'a>>(c: &mut Context, input: &'a str) -> Result<V, Error> {
+    // The whitespace after the value is taken separately, since a number at the end of the input
+    // would otherwise be undecided
+    let (json, rest) =
+        state::trace("value", state::preceded(lift(whitespace), element))(c, input).finish()?;
+    let rest = whitespace(rest).finish()?.1;
+    match rest.is_empty() {
+        true => Ok(json),
+        false => Err(Error::new(&rest, Expected::Label("end of document"))),
     }
 }
 
@@ -39,200 +239,384 @@ fn whitespace(i: &str) -> Parse<&str, &str> {
     })(i)
 }
 
-fn null(i: &str) -> Parse<&str, JSON> {
-    map(literal("null"), |_| JSON::Null)(i)
+fn null(i: &str) -> Parse<&str, JsonRef<'_>> {
+    map(literal("null"), |_| JsonRef::Null)(i)
 }
 
-fn bool(i: &str) -> Parse<&str, JSON> {
+fn bool(i: &str) -> Parse<&str, JsonRef<'_>> {
     either(
-        map(literal("true"), |_| JSON::Bool(true)),
-        map(literal("false"), |_| JSON::Bool(false)),
+        map(literal("true"), |_| JsonRef::Bool(true)),
+        map(literal("false"), |_| JsonRef::Bool(false)),
     )(i)
 }
 
-fn value(i: &str) -> Parse<&str, JSON> {
-    between(
-        whitespace,
-        either_of(&[string, number, object, array, bool, null][..]),
-        whitespace,
-    )(i)
+fn value<'a, V: Build<'a>>(c: &mut Context, i: &'a str) -> Parse<&'a str, V> {
+    state::trace(
+        "value",
+        state::between(lift(whitespace), element, lift(whitespace)),
+    )(c, i)
 }
 
-fn array(i: &str) -> Parse<&str, JSON> {
-    between(
-        char('['),
-        map(separated_items(char(','), value), |arr| JSON::Array(arr)),
-        char(']'),
-    )(i)
+/// A value without the whitespace around it
+/// Arrays and objects are told apart by their bracket, which saves the stack of trying the other
+/// values first at each level of nesting.
+fn element<'a, V: Build<'a>>(c: &mut Context, i: &'a str) -> Parse<&'a str, V> {
+    match i.as_bytes().first() {
+        Some(b'[') => return array(c, i),
+        Some(b'{') => return object(c, i),
+        _ => (),
+    }
+    state::either(
+        lift(map(either(string, number), V::scalar)),
+        state::either(
+            object,
+            state::either(array, lift(map(either(bool, null), V::scalar))),
+        ),
+    )(c, i)
 }
 
-fn object(i: &str) -> Parse<&str, JSON> {
-    between(
-        char('{'),
-        map(
-            separated_items(
-                char(','),
-                around(between(whitespace, string, whitespace), char(':'), value),
-            ),
-            |m| {
-                let mut map = HashMap::new();
-                for (ks, v) in m {
-                    if let JSON::String(k) = ks {
-                        map.insert(k, v);
-                    } else {
-                        panic!();
-                    }
-                }
-                JSON::Object(map)
-            },
+fn array<'a, V: Build<'a>>(c: &mut Context, i: &'a str) -> Parse<&'a str, V> {
+    state::trace(
+        "array",
+        state::label(
+            state::preceded(lift(char('[')), nested(array_items)),
+            "array",
         ),
-        char('}'),
-    )(i)
+    )(c, i)
 }
 
-fn number(i: &str) -> Parse<&str, JSON> {
-    map_result(
-        serial4(
-            // Negative
-            map(optional(char('-')), |s| match s {
-                Some('-') => '-',
-                None => '+',
-                _ => unreachable!(),
-            }),
-            // 0 or number
-            either(
-                map(char('0'), |c| c.to_string()),
-                map(
-                    serial(
-                        char_of("123456789"),
-                        take_while(|c| "0123456789".contains(c)),
-                    ),
-                    |(a, b)| format!("{a}{b}"),
-                ),
-            ),
-            // Decimals
-            optional(preceded(
-                char('.'),
-                take_some_while(|c| "0123456789".contains(c)),
-            )),
-            // Exponent
-            optional(map(
-                serial3(
-                    either(char('e'), char('E')),
-                    optional(either(char('+'), char('-'))),
-                    take_some_while(|c| "0123456789".contains(c)),
-                ),
-                |(e, os, n)| {
-                    if let Some(s) = os {
-                        format!("{e}{s}{n}")
-                    } else {
-                        format!("{e}{n}")
-                    }
-                },
-            )),
+fn array_items<'a, V: Build<'a>>(c: &mut Context, i: &'a str) -> Parse<&'a str, V> {
+    state::either(
+        lift(map(preceded(whitespace, char(']')), |_| V::array(vec![]))),
+        state::terminated(state::map(members(item(']')), V::array), lift(char(']'))),
+    )(c, i)
+}
+
+fn object<'a, V: Build<'a>>(c: &mut Context, i: &'a str) -> Parse<&'a str, V> {
+    state::trace(
+        "object",
+        state::label(
+            state::preceded(lift(char('{')), nested(object_members)),
+            "object",
         ),
-        |res| -> Result<JSON, &str> {
-            match res {
-                // Integer
-                (sign, number, None, None) => {
-                    if let Ok(n) = number.parse() {
-                        match sign {
-                            '+' => Ok(JSON::UnsignedInt(n)),
-                            '-' => Ok(JSON::SignedInt(-(n as isize))),
-                            _ => unreachable!(),
+    )(c, i)
+}
+
+fn object_members<'a, V: Build<'a>>(c: &mut Context, i: &'a str) -> Parse<&'a str, V> {
+    state::either(
+        lift(map(preceded(whitespace, char('}')), |_| {
+            V::object(HashMap::new())
+        })),
+        state::terminated(
+            state::map_state(
+                members(state::serial(lift(terminated(key, char(':'))), item('}'))),
+                |c: &mut Context, m| {
+                    let mut map = HashMap::new();
+                    for ((start, k), v) in m {
+                        if map.insert(V::Key::from(k), v).is_some() {
+                            c.diagnostics
+                                .push(Error::new(&start, Expected::Label("unique key")));
                         }
-                    } else {
-                        Err("fisk")
                     }
-                }
+                    V::object(map)
+                },
+            ),
+            lift(char('}')),
+        ),
+    )(c, i)
+}
 
-                // Float
-                (sign, number, Some(decimal), exponent) => {
-                    let exponent = exponent.unwrap_or("".to_string());
-                    if let Ok(n) = format!("{sign}{number}.{decimal}{exponent}").parse() {
-                        Ok(JSON::Float(n))
-                    } else {
-                        Err("fisk")
-                    }
+/// An object key, along with the input it starts at
+fn key(i: &str) -> Parse<&str, (&str, Cow<'_, str>)> {
+    map(
+        between(whitespace, serial(position, string), whitespace),
+        |(start, k)| match k {
+            JsonRef::String(k) => (start, k),
+            _ => unreachable!(),
+        },
+    )(i)
+}
+
+/// Counts how deeply arrays and objects nest, halting when deeper than the context allows
+fn nested<'a, V>(
+    p: impl Fn(&mut Context, &'a str) -> Parse<&'a str, V>,
+) -> impl Fn(&mut Context, &'a str) -> Parse<&'a str, V> {
+    move |c: &mut Context, i: &'a str| {
+        if c.depth == c.max_depth {
+            return Parse::Halt(Error::new(&i, Expected::Label("shallower nesting")));
+        }
+        c.depth += 1;
+        let result = p(c, i);
+        c.depth -= 1;
+        result
+    }
+}
+
+/// Comma separated array items or object members, at least one and at most `MAX_MEMBERS`
+/// The bracket or comma before a member commits to it, so an invalid member halts, and the
+/// error is where the member is invalid rather than at the closing bracket.
+fn members<'a, O>(
+    member: impl Fn(&mut Context, &'a str) -> Parse<&'a str, O>,
+) -> impl Fn(&mut Context, &'a str) -> Parse<&'a str, Vec<O>> {
+    state::separated_range(
+        1,
+        MAX_MEMBERS,
+        Trailing::Forbid,
+        lift(char(',')),
+        state::halt(member),
+    )
+}
+
+/// An array item or object value, which is followed by a comma or the `close` bracket
+/// When recovering from errors, an invalid value is replaced with `null`, and anything else before
+/// the comma or bracket is skipped, like a missing comma and the values after it.
+fn item<'a, V: Build<'a>>(close: char) -> impl Fn(&mut Context, &'a str) -> Parse<&'a str, V> {
+    let next = move |i| either(char(','), char(close))(i);
+    state::terminated(
+        state::recover_with(value, next, V::scalar(JsonRef::Null)),
+        lift(recover_with(map(peek(next), |_| ()), next, ())),
+    )
+}
+
+fn number(i: &str) -> Parse<&str, JsonRef<'_>> {
+    trace("number", label(number_parts, "number"))(i)
+}
+
+fn number_parts(i: &str) -> Parse<&str, JsonRef<'_>> {
+    map_result(
+        number_token,
+        |token| match number_value(token) {
+            Parse::Success(json, "") => Ok(json),
+            _ => Err(()),
+        },
+        "number",
+    )(i)
+}
+
+/// The characters a number can hold, which are taken before the number is checked
+/// A number ends where these characters do, so this decides where it ends even at the end of the input.
+fn number_token(i: &str) -> Parse<&str, &str> {
+    take_some_while(|c: char| c.is_ascii_digit() || "+-.eE".contains(c))(i)
+}
+
+/// Converts a number token, which is complete, so the parts accept limits
+fn number_value(i: &str) -> Parse<&str, JsonRef<'_>> {
+    let digits = || accept_limit(take_some_while(|c: char| c.is_ascii_digit()));
+    // Negative
+    let sign = char('-').optional().map(|s| match s {
+        Some('-') => '-',
+        None => '+',
+        _ => unreachable!(),
+    });
+    // 0 or number
+    let integer = char('0')
+        .map(|c: char| c.to_string())
+        .or(char_of("123456789")
+            .then(accept_limit(take_while(|c: char| c.is_ascii_digit())))
+            .map(|(a, b)| format!("{a}{b}")));
+    let decimals = digits().preceded_by(accept_limit(char('.'))).optional();
+    let exponent = accept_limit(char_of("eE"))
+        .then(accept_limit(char_of("+-")).optional())
+        .then(digits())
+        .map(|((e, os), n)| match os {
+            Some(s) => format!("{e}{s}{n}"),
+            None => format!("{e}{n}"),
+        })
+        .optional();
+
+    serial!(sign, integer, decimals, exponent).map_result(
+        |(sign, number, decimal, exponent)| -> Result<JsonRef<'_>, ParseFloatError> {
+            let integer = match (sign, &decimal, &exponent) {
+                ('+', None, None) => number.parse().ok().map(JsonRef::UnsignedInt),
+                // The sign is parsed with the digits, since the smallest integer has no positive
+                ('-', None, None) => format!("-{number}").parse().ok().map(JsonRef::SignedInt),
+                _ => None,
+            };
+            match integer {
+                Some(json) => Ok(json),
+                // Floats, and integers too large for the integer types
+                None => {
+                    let decimal = decimal.unwrap_or("0");
+                    let exponent = exponent.unwrap_or_default();
+                    format!("{sign}{number}.{decimal}{exponent}")
+                        .parse()
+                        .map(JsonRef::Float)
                 }
-                _ => unreachable!(),
             }
         },
-        "e",
+        "number",
     )(i)
 }
 
 // JSON string
 // Currently accepts raw UTF-8
-fn string(i: &str) -> Parse<&str, JSON> {
-    map(
-        between(
-            char('"'),
-            repeat_any(either(
-                // Normal characters
-                map(other_than("\"\\"), |s: &str| s.to_string()),
-                // Backslash escaped
-                preceded(
-                    char('\\'),
-                    halt(either(
-                        // Escaped characters
-                        map_result(
-                            pop,
-                            |c| {
-                                match match c {
-                                    '"' => Ok('"'),    // Quote
-                                    '\\' => Ok('\\'),  // Backslash
-                                    '/' => Ok('/'),    // Farward slash
-                                    'b' => Ok('\x08'), // BS Backspace
-                                    'f' => Ok('\x0C'), // FF Form feed
-                                    'n' => Ok('\n'),   // LF Line Feed
-                                    'r' => Ok('\r'),   // CR Carriage Return
-                                    't' => Ok('\t'),   // HT Horizontal Tab
-                                    _ => Err(()),
-                                } {
-                                    Ok(c) => Ok(c.to_string()),
-                                    Err(e) => Err(e),
-                                }
-                            },
-                            "escaped character",
-                        ),
-                        // UTF-8 hex characters
-                        preceded(
-                            char('u'),
-                            halt(map_result(
-                                take(4),
-                                |h| -> Result<String, ()> {
-                                    println!("h: {h}");
-                                    Ok(char::from_u32(u32::from_str_radix(h, 16).or(Err(()))?)
-                                        .ok_or(())?
-                                        .to_string())
-                                },
-                                "hex formatted unicode",
-                            )),
-                        ),
-                    )),
-                ),
-            )),
-            char('"'),
+fn string(i: &str) -> Parse<&str, JsonRef<'_>> {
+    trace("string", label(string_characters, "string"))(i)
+}
+
+fn string_characters(i: &str) -> Parse<&str, JsonRef<'_>> {
+    map(string_text, JsonRef::String)(i)
+}
+
+fn string_text(i: &str) -> Parse<&str, Cow<'_, str>> {
+    between(
+        char('"'),
+        escaped_transform(take_class(&UNESCAPED), '\\', escape),
+        char('"'),
+    )(i)
+}
+
+/// What follows a backslash in a string
+fn escape(i: &str) -> Parse<&str, char> {
+    either(
+        // Escaped characters
+        map_result(
+            pop,
+            |c| match c {
+                '"' => Ok('"'),    // Quote
+                '\\' => Ok('\\'),  // Backslash
+                '/' => Ok('/'),    // Farward slash
+                'b' => Ok('\x08'), // BS Backspace
+                'f' => Ok('\x0C'), // FF Form feed
+                'n' => Ok('\n'),   // LF Line Feed
+                'r' => Ok('\r'),   // CR Carriage Return
+                't' => Ok('\t'),   // HT Horizontal Tab
+                _ => Err(()),
+            },
+            "escaped character",
         ),
-        |s| JSON::String(s.join("")),
+        // UTF-16 code units in hex
+        preceded(char('u'), halt(unicode)),
     )(i)
 }
 
+/// A character as one code unit, or as a surrogate pair of two escaped code units
+fn unicode(i: &str) -> Parse<&str, char> {
+    let high = map_bool(hex_unit, |u| (0xD800..0xDC00).contains(u));
+    let low = map_bool(hex_unit, |u| (0xDC00..0xE000).contains(u));
+    either(
+        map_option(
+            serial(high, preceded(literal("\\u"), low)),
+            |(high, low)| char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)),
+        ),
+        // Lone surrogates aren't characters
+        map_option(hex_unit, char::from_u32),
+    )(i)
+}
+
+/// Four hex digits
+fn hex_unit(i: &str) -> Parse<&str, u32> {
+    map_result(
+        take(4),
+        |h: &str| match h.bytes().all(|b| b.is_ascii_hexdigit()) {
+            true => u32::from_str_radix(h, 16).or(Err(())),
+            false => Err(()),
+        },
+        "hex formatted unicode",
+    )(i)
+}
+
+/// Prints a value the way `element` parses it, failing when deeper than `MAX_DEPTH`
+fn print_value(json: &JSON, depth: usize, out: &mut String) -> bool {
+    let nested = matches!(json, JSON::Array(_) | JSON::Object(_));
+    if nested && depth == MAX_DEPTH {
+        return false;
+    }
+    match json {
+        JSON::String(s) => print_string(s, out),
+        JSON::Bool(true) => {
+            out.push_str("true");
+            true
+        }
+        JSON::Bool(false) => {
+            out.push_str("false");
+            true
+        }
+        JSON::Null => {
+            out.push_str("null");
+            true
+        }
+        JSON::Array(items) => {
+            out.push('[');
+            let printed = items.iter().enumerate().all(|(n, item)| {
+                if n > 0 {
+                    out.push(',');
+                }
+                print_value(item, depth + 1, out)
+            });
+            out.push(']');
+            printed
+        }
+        JSON::Object(members) => {
+            // Printed from sorted references to the members, so nothing is copied
+            let mut members: Vec<_> = members.iter().collect();
+            members.sort_by_key(|(k, _)| *k);
+            out.push('{');
+            let printed = members.into_iter().enumerate().all(|(n, (k, v))| {
+                if n > 0 {
+                    out.push(',');
+                }
+                print_string(k, out);
+                out.push(':');
+                print_value(v, depth + 1, out)
+            });
+            out.push('}');
+            printed
+        }
+        _ => print_number(json, out),
+    }
+}
+
+fn print_string(s: &str, out: &mut String) -> bool {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\x08' => out.push_str("\\b"),
+            '\x0C' => out.push_str("\\f"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    true
+}
+
+fn print_number(json: &JSON, out: &mut String) -> bool {
+    match json {
+        JSON::UnsignedInt(n) => write!(out, "{n}").unwrap(),
+        // Zero keeps its sign, which makes it signed when parsed
+        JSON::SignedInt(0) => out.push_str("-0"),
+        JSON::SignedInt(n) => write!(out, "{n}").unwrap(),
+        // Debug formatting keeps the decimal point, and is exact
+        JSON::Float(n) if n.is_finite() => write!(out, "{n:?}").unwrap(),
+        _ => return false,
+    }
+    true
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parse::abnf::{Grammar, Tree};
+    use crate::parse::generate::Generator;
+    use crate::parse::stream::Stream;
+    use crate::parse::trace::with_trace;
+    use std::time::Instant;
 
     #[test]
     fn test_null() {
-        println!("{:?}", null("null"));
-        assert!(true);
+        assert_eq!(null("null"), Parse::Success(JsonRef::Null, ""));
     }
 
     #[test]
     fn test_bool() {
-        assert_eq!(bool("truem"), Parse::Success(JSON::Bool(true), "m"));
-        assert_eq!(bool("false"), Parse::Success(JSON::Bool(false), ""));
+        assert_eq!(bool("truem"), Parse::Success(JsonRef::Bool(true), "m"));
+        assert_eq!(bool("false"), Parse::Success(JsonRef::Bool(false), ""));
         assert!(bool("s").is_retreat());
     }
 
@@ -253,7 +637,460 @@ mod tests {
             ]),
         );
 
-        let result = object("{\"something\": false, \"something else\": [true, false, \"hello\", 3, {\"fisk\": 3}]}");
-        assert_eq!(result, Parse::Success(JSON::Object(expected), ""));
+        let result = object::<JSON>(&mut Context::default(), "{\"something\": false, \"something else\": [true, false, \"hello\", 3, {\"fisk\": 3}]}");
+        assert_eq!(result.finish(), Ok((JSON::Object(expected), "")));
+    }
+
+    #[test]
+    fn test_json_ref() {
+        let input = r#"{"plain": ["a", "b\tc"], "esc\"aped": null}"#;
+        let json = JsonRef::try_from(input).unwrap();
+        let JsonRef::Object(members) = &json else {
+            panic!("{json:?}");
+        };
+        assert!(matches!(
+            members.keys().find(|k| k.as_ref() == "plain"),
+            Some(Cow::Borrowed(_))
+        ));
+        assert!(matches!(
+            members.keys().find(|k| k.as_ref() == "esc\"aped"),
+            Some(Cow::Owned(_))
+        ));
+        assert_eq!(
+            members["plain"],
+            JsonRef::Array(vec![
+                JsonRef::String(Cow::Borrowed("a")),
+                JsonRef::String(Cow::Owned("b\tc".to_string())),
+            ])
+        );
+        assert_eq!(json.into_owned(), input.parse::<JSON>().unwrap());
+    }
+
+    #[test]
+    fn test_number() {
+        assert_eq!(number("0,"), Parse::Success(JsonRef::UnsignedInt(0), ","));
+        assert_eq!(number("-12,"), Parse::Success(JsonRef::SignedInt(-12), ","));
+        assert_eq!(number("1.5,"), Parse::Success(JsonRef::Float(1.5), ","));
+        assert_eq!(
+            number("-2.5E-1,"),
+            Parse::Success(JsonRef::Float(-0.25), ",")
+        );
+        assert_eq!(number("1e2,"), Parse::Success(JsonRef::Float(100.0), ","));
+        assert!(number("-a").is_retreat());
+
+        // The integers at the ends of the range, and past them
+        assert_eq!(
+            "-9223372036854775808".parse::<JSON>(),
+            Ok(JSON::SignedInt(isize::MIN))
+        );
+        assert_eq!(
+            "[-9223372036854775808]".parse::<JSON>(),
+            Ok(JSON::Array(vec![JSON::SignedInt(isize::MIN)]))
+        );
+        assert_eq!(
+            "18446744073709551615".parse::<JSON>(),
+            Ok(JSON::UnsignedInt(usize::MAX))
+        );
+        assert_eq!(
+            "99999999999999999999".parse::<JSON>(),
+            Ok(JSON::Float(1e20))
+        );
+        assert_eq!(
+            "-9223372036854775809".parse::<JSON>(),
+            Ok(JSON::Float(-9223372036854775809.0))
+        );
+    }
+
+    #[test]
+    fn test_number_token() {
+        // A number at the end of the input is undecided, since more digits could follow
+        assert_eq!(
+            number("12"),
+            Parse::Limit(Some(JsonRef::UnsignedInt(12)), "")
+        );
+        assert_eq!(number("1.5e"), Parse::Limit(None, ""));
+        assert_eq!(number("12 "), Parse::Success(JsonRef::UnsignedInt(12), " "));
+        // The whole token must be a number
+        assert!(number("01,").is_retreat());
+        assert!(number("1-2,").is_retreat());
+        assert!(number("1.5e,").is_retreat());
+        assert_eq!(
+            number_value("1.5e"),
+            Parse::Success(JsonRef::Float(1.5), "e")
+        );
+
+        // A document decides the number at its end, with or without whitespace after it
+        assert_eq!("12".parse::<JSON>(), Ok(JSON::UnsignedInt(12)));
+        assert_eq!(" -0.5e1 \n".parse::<JSON>(), Ok(JSON::Float(-5.0)));
+        let input = "1 2";
+        let error = input.parse::<JSON>().unwrap_err();
+        assert_eq!(error.offset(&input), 2);
+        assert_eq!(error.expected(), &[Expected::Label("end of document")]);
+        assert!("01".parse::<JSON>().is_err());
+    }
+
+    #[test]
+    fn test_string() {
+        let string = |i| string(i).finish().map(|(json, _)| json);
+        assert_eq!(
+            string(r#""a\u00e6\uE000\uffff""#),
+            Ok(JsonRef::String("a\u{e6}\u{e000}\u{ffff}".into()))
+        );
+        assert_eq!(
+            string(r#""\ud83d\ude00\uD83D\uDE00""#),
+            Ok(JsonRef::String("\u{1f600}\u{1f600}".into()))
+        );
+        // Surrogates must be in pairs of a high and a low one
+        for input in [
+            r#""\ud83d""#,
+            r#""\ud83dx""#,
+            r#""\ude00\ud83d""#,
+            r#""\ud83d\u0041""#,
+        ] {
+            assert!(string(input).is_err(), "{input}");
+        }
+        assert!(string(r#""\u+041""#).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(debug_assertions),
+        ignore = "tracing is compiled out of release builds"
+    )]
+    fn test_trace() {
+        let (_, trace) = with_trace(|| value::<JSON>(&mut Context::default(), "[1]"));
+        let calls: Vec<_> = trace.calls().iter().map(|c| (c.name, c.depth)).collect();
+        assert_eq!(
+            calls,
+            vec![
+                ("value", 0),
+                ("array", 1),
+                ("value", 2),
+                ("string", 3),
+                ("number", 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let input = "[1, tru, {\"a\": x, \"b\": 2}, []]";
+        let (json, errors) = JSON::parse_partial(input);
+        let mut object = HashMap::new();
+        object.insert("a".to_string(), JSON::Null);
+        object.insert("b".to_string(), JSON::UnsignedInt(2));
+        assert_eq!(
+            json,
+            Some(JSON::Array(vec![
+                JSON::UnsignedInt(1),
+                JSON::Null,
+                JSON::Object(object),
+                JSON::Array(vec![]),
+            ]))
+        );
+        let offsets: Vec<_> = errors.iter().map(|e| e.offset(&input)).collect();
+        assert_eq!(offsets, vec![4, 15]);
+
+        assert_eq!(
+            JSON::parse_partial("{ }"),
+            (Some(JSON::Object(HashMap::new())), vec![])
+        );
+        let (json, errors) = JSON::parse_partial("[1] x");
+        assert_eq!(json, None);
+        assert_eq!(errors.len(), 1);
+
+        // Values after a missing comma are skipped, without errors from values in them
+        let offsets = |input: &str| -> (Option<JSON>, Vec<usize>) {
+            let (json, errors) = JSON::parse_partial(input);
+            (json, errors.iter().map(|e| e.offset(&input)).collect())
+        };
+        let (json, errors) = offsets("[ [1 2], 3]");
+        let one = JSON::Array(vec![JSON::UnsignedInt(1)]);
+        assert_eq!(json, Some(JSON::Array(vec![one, JSON::UnsignedInt(3)])));
+        assert_eq!(errors, vec![5]);
+        let (json, errors) = offsets(r#"{"a": [1, "x" "y" 2], "b": 2}"#);
+        let JSON::Object(object) = json.unwrap() else {
+            panic!()
+        };
+        assert_eq!(object.len(), 2);
+        assert_eq!(errors, vec![14]);
+        let (json, errors) = offsets(r#"["a,b" x, 2]"#);
+        let items = vec![JSON::String("a,b".to_string()), JSON::UnsignedInt(2)];
+        assert_eq!(json, Some(JSON::Array(items)));
+        assert_eq!(errors, vec![7]);
+    }
+
+    #[test]
+    fn test_context() {
+        let mut context = Context::new(2);
+        assert!(JSON::parse_with("[[1], {\"a\": 2}]", &mut context).is_ok());
+        let input = "[[[1]]]";
+        let error = JSON::parse_with(input, &mut context).unwrap_err();
+        assert_eq!(error.offset(&input), 3);
+        assert_eq!(error.expected(), &[Expected::Label("shallower nesting")]);
+
+        // The default limit fits in the stack of a test thread, along with the error one past it
+        for (depth, valid) in [(MAX_DEPTH, true), (MAX_DEPTH + 1, false)] {
+            let input = "[".repeat(depth) + &"]".repeat(depth);
+            assert_eq!(input.parse::<JSON>().is_ok(), valid);
+            let input = "{\"a\":".repeat(depth) + "1" + &"}".repeat(depth);
+            assert_eq!(input.parse::<JSON>().is_ok(), valid);
+        }
+
+        let input = "{\"a\": 1, \"b\": {\"a\": 2}, \"a\": 3}";
+        let mut context = Context::default();
+        let json = JSON::parse_with(input, &mut context).unwrap();
+        assert!(matches!(json, JSON::Object(o) if o["a"] == JSON::UnsignedInt(3)));
+        let offsets: Vec<_> = context
+            .diagnostics()
+            .iter()
+            .map(|e| e.offset(&input))
+            .collect();
+        assert_eq!(offsets, vec![24]);
+    }
+
+    /// The JSON grammar of RFC 8259
+    const RFC_8259: &str = "\
+JSON-text = ws value ws
+begin-array = ws %x5B ws
+begin-object = ws %x7B ws
+end-array = ws %x5D ws
+end-object = ws %x7D ws
+name-separator = ws %x3A ws
+value-separator = ws %x2C ws
+ws = *( %x20 / %x09 / %x0A / %x0D )
+value = false / null / true / object / array / number / string
+false = %x66.61.6c.73.65
+null = %x6e.75.6c.6c
+true = %x74.72.75.65
+object = begin-object [ member *( value-separator member ) ] end-object
+member = string name-separator value
+array = begin-array [ value *( value-separator value ) ] end-array
+number = [ minus ] int [ frac ] [ exp ]
+decimal-point = %x2E
+digit1-9 = %x31-39
+e = %x65 / %x45
+exp = e [ minus / plus ] 1*DIGIT
+frac = decimal-point 1*DIGIT
+int = zero / ( digit1-9 *DIGIT )
+minus = %x2D
+plus = %x2B
+zero = %x30
+string = quotation-mark *char quotation-mark
+char = unescaped / escape ( %x22 / %x5C / %x2F / %x62 / %x66 / %x6E / %x72 / %x74 / %x75 hex-escape )
+hex-escape = 4HEXDIG
+escape = %x5C
+quotation-mark = %x22
+unescaped = %x20-21 / %x23-5B / %x5D-10FFFF
+";
+
+    /// Number of values in the document, counting itself
+    fn values(json: &JSON) -> usize {
+        match json {
+            JSON::Array(items) => 1 + items.iter().map(values).sum::<usize>(),
+            JSON::Object(members) => 1 + members.values().map(values).sum::<usize>(),
+            _ => 1,
+        }
+    }
+
+    /// Number of matches of the rule in the tree, counting those within each other
+    fn matches(tree: &Tree, rule: &str) -> usize {
+        let inner: usize = tree.children().iter().map(|c| matches(c, rule)).sum();
+        inner + (tree.rule() == rule) as usize
+    }
+
+    /// Whether the `\u` escapes of the document are characters or surrogate pairs
+    /// The grammar allows lone surrogates, which `JSON` rejects since they aren't characters.
+    fn paired(input: &str, tree: &Tree) -> bool {
+        let mut units = tree
+            .find_all("hex-escape")
+            .into_iter()
+            .map(|t| {
+                let at = t.text().as_ptr() as usize - input.as_ptr() as usize;
+                (at, u32::from_str_radix(t.text(), 16).unwrap())
+            })
+            .peekable();
+        while let Some((at, unit)) = units.next() {
+            // A low surrogate right after the four digits and the `\u` of its escape
+            let low =
+                |&(next, unit): &(usize, u32)| next == at + 6 && (0xDC00..0xE000).contains(&unit);
+            match unit {
+                0xD800..0xDC00 if units.next_if(low).is_some() => (),
+                0xD800..0xE000 => return false,
+                _ => (),
+            }
+        }
+        true
+    }
+
+    #[test]
+    fn test_generated() {
+        let grammar: Grammar = RFC_8259.parse().unwrap();
+        let mut generator = Generator::new(&grammar, 8259).max_depth(10).max_repeat(3);
+        for _ in 0..200 {
+            let input = generator.generate("JSON-text");
+            let (tree, _) = grammar.parser("JSON-text")(&input).finish().unwrap();
+            let mut context = Context::default();
+            let json = JSON::parse_with(&input, &mut context);
+            assert_eq!(json.is_ok(), paired(&input, &tree), "{input:?}");
+            if json.is_err() {
+                continue;
+            }
+            // Values of duplicate keys are dropped, which is reported as a diagnostic
+            if context.diagnostics().is_empty() {
+                assert_eq!(
+                    values(json.as_ref().unwrap()),
+                    matches(&tree, "value"),
+                    "{input:?}"
+                );
+            }
+
+            // Printed documents parse back to the same value, unless they have floats JSON
+            // can't represent
+            let json = json.unwrap();
+            if let Some(text) = json.to_text() {
+                assert_eq!(text.parse::<JSON>().as_ref(), Ok(&json), "{input:?}");
+                let parsed = JSON::syntax().parse(&text).finish();
+                assert_eq!(parsed, Ok((json, "")), "{input:?}");
+            }
+
+            // Invalid input is rejected without panicking
+            let mutated = generator.mutate(&input);
+            if mutated.parse::<JSON>().is_ok() {
+                assert!(grammar.matches("JSON-text", &mutated), "{mutated:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_text() {
+        let input = r#" {"b": [1, -0, -2.5, 1e300], "a": {"\u0001\"\n": [true, null, []]}} "#;
+        let json: JSON = input.parse().unwrap();
+        let text = json.to_text().unwrap();
+        assert_eq!(
+            text,
+            r#"{"a":{"\u0001\"\n":[true,null,[]]},"b":[1,-0,-2.5,1e300]}"#
+        );
+        assert_eq!(text.parse::<JSON>(), Ok(json.clone()));
+        assert_eq!(JSON::syntax().parse(input).finish(), Ok((json, " ")));
+        assert_eq!(JSON::Float(f64::NAN).to_text(), None);
+
+        // The syntax parses with the rules of a document, so it fails the same way
+        let input = r#"{"a": [1, 2 3]}"#;
+        assert_eq!(
+            JSON::syntax().parse(input).finish(),
+            input.parse::<JSON>().map(|json| (json, ""))
+        );
+    }
+
+    #[test]
+    fn test_syntax_depth() {
+        let nest = |depth| (0..depth).fold(JSON::Null, |json, _| JSON::Array(vec![json]));
+        let text = "[".repeat(MAX_DEPTH) + "null" + &"]".repeat(MAX_DEPTH);
+        assert_eq!(nest(MAX_DEPTH).to_text(), Some(text.clone()));
+        assert_eq!(
+            JSON::syntax().parse(&text).finish(),
+            Ok((nest(MAX_DEPTH), ""))
+        );
+        assert_eq!(nest(MAX_DEPTH + 1).to_text(), None);
+        let object = JSON::Object(HashMap::from([("a".to_string(), nest(MAX_DEPTH))]));
+        assert_eq!(object.to_text(), None);
+        let text = "[".repeat(100_000);
+        let error = JSON::syntax().parse(&text).finish().unwrap_err();
+        assert_eq!(error.offset(&text.as_str()), MAX_DEPTH + 1);
+        assert_eq!(error.expected(), &[Expected::Label("shallower nesting")]);
+    }
+
+    #[test]
+    fn test_stream() {
+        fn document(i: &str) -> Parse<&str, JSON> {
+            preceded(whitespace, JSON::parse)(i)
+        }
+        let mut stream = Stream::new();
+        let mut documents = Vec::new();
+        for chunk in "[1, 2]\n{\"å\": \"b\"}\n".as_bytes().chunks(3) {
+            stream.feed(chunk);
+            while let Some(document) = stream.parse_str(document).unwrap() {
+                documents.push(document);
+            }
+        }
+        let mut object = HashMap::new();
+        object.insert("å".to_string(), JSON::String("b".to_string()));
+        assert_eq!(
+            documents,
+            vec![
+                JSON::Array(vec![JSON::UnsignedInt(1), JSON::UnsignedInt(2)]),
+                JSON::Object(object)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_str_errors() {
+        assert_eq!(
+            "[1, true]".parse(),
+            Ok(JSON::Array(vec![JSON::UnsignedInt(1), JSON::Bool(true)]))
+        );
+
+        let input = "[1, tru]";
+        let error = input.parse::<JSON>().unwrap_err();
+        assert_eq!(error.offset(&input), 4);
+        assert!(error.expected().contains(&Expected::Literal("true")));
+        assert_eq!(error.context().last(), Some(&"array"));
+
+        // Errors in nested containers and members are where the member is invalid
+        let input = "[[1, 2 3]]";
+        let error = input.parse::<JSON>().unwrap_err();
+        assert_eq!(error.offset(&input), 7);
+        assert_eq!(
+            error.expected(),
+            &[Expected::Char(','), Expected::Char(']')]
+        );
+        assert_eq!(error.context(), &["array", "array"]);
+        let input = r#"{"a" 1}"#;
+        let error = input.parse::<JSON>().unwrap_err();
+        assert_eq!(error.offset(&input), 5);
+        assert_eq!(error.expected(), &[Expected::Char(':')]);
+        assert_eq!(error.context(), &["object"]);
+        let input = "[1,]";
+        assert_eq!(input.parse::<JSON>().unwrap_err().offset(&input), 3);
+
+        let input = "[1, \"a\\x\"]";
+        let error = input.parse::<JSON>().unwrap_err();
+        assert_eq!(error.offset(&input), 7);
+        assert_eq!(
+            error.expected(),
+            &[Expected::Label("escaped character"), Expected::Char('u')]
+        );
+        assert_eq!(error.context(), &["string", "array"]);
+
+        let input = " ?";
+        let error = input.parse::<JSON>().unwrap_err();
+        assert_eq!(error.offset(&input), 1);
+        assert!(error.expected().contains(&Expected::Label("string")));
+        assert!(error.expected().contains(&Expected::Label("number")));
+
+        let input = "[1] x";
+        let error = input.parse::<JSON>().unwrap_err();
+        assert_eq!(error.offset(&input), 4);
+        assert_eq!(error.expected(), &[Expected::Label("end of document")]);
+    }
+
+    /// Times parsing a document where many alternatives retreat before one succeeds
+    /// Run with `cargo test --release -- --ignored`, the fastest of 20 parses is shown.
+    #[test]
+    #[ignore]
+    fn bench_parse() {
+        let item = r#"{"id": 12345, "name": "Ada \"Lovelace\"", "tags": ["a", "b", "c"],
+            "scores": [1.5, -2, 3e10, 0], "active": true, "parent": null, "extra": {}}"#;
+        let input = format!("[{}]", vec![item; 5_000].join(", "));
+        let fastest = (0..20)
+            .map(|_| {
+                let start = Instant::now();
+                assert!(input.parse::<JSON>().is_ok());
+                start.elapsed()
+            })
+            .min();
+        eprintln!("parse of {} bytes: {:?}", input.len(), fastest.unwrap());
     }
 }
@@ -1,13 +1,19 @@
 use parse::str::literal;
 use std::collections::HashMap;
+use std::io::{self, Read, Write};
 use std::net::{TcpListener, TcpStream};
+use std::process::ExitCode;
 
 mod http;
 mod json;
+// A library of combinators, of which the site's parsers use some
+#[allow(dead_code)]
 mod parse;
 mod url;
+// Not used by requests yet
+#[allow(dead_code)]
 mod urlv2;
-use urlv2::primitives::dec_octet;
+use parse::diagnostic::Diagnostic;
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -21,10 +27,49 @@ pub struct Request<'a> {
     unread_stream: &'a mut TcpStream,
 }
 
-fn main() {
-    println!("{:?}", dec_octet("10"));
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some("check-json") = args.get(1).map(String::as_str) {
+        return check_json(args.get(2).map(String::as_str));
+    }
 
     //serve();
+    ExitCode::SUCCESS
+}
+
+/// Checks that a file, or the standard input if no path is given, is a valid JSON document
+/// Shows where the document is invalid if it isn't.
+fn check_json(path: Option<&str>) -> ExitCode {
+    let text = match path {
+        Some(path) => std::fs::read_to_string(path),
+        None => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).map(|_| text)
+        }
+    };
+    let text = match text {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Could not read the document: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match json_diagnostic(&text) {
+        None => {
+            println!("The document is valid JSON");
+            ExitCode::SUCCESS
+        }
+        Some(diagnostic) => {
+            eprintln!("{diagnostic}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Where the document is invalid, if it isn't valid JSON
+fn json_diagnostic(text: &str) -> Option<String> {
+    let error = text.parse::<json::JSON>().err()?;
+    Some(Diagnostic::new(&text, &error).to_string())
 }
 
 fn serve() {
@@ -44,9 +89,34 @@ fn serve() {
 }
 
 fn handle_client(mut stream: TcpStream) {
-    if let Ok(request) = http::parse_heads(&mut stream) {
-        println!("Got request: {:?}", request);
-    } else {
-        eprintln!("There was an error parsing the request")
+    let error = match http::parse_heads(&mut stream) {
+        Ok(request) => {
+            println!("Got request: {:?}", request);
+            return;
+        }
+        Err(error) => error,
+    };
+    eprintln!("There was an error parsing the request: {}", error.message);
+    if let Err(e) = stream.write_all(http::bad_request(&error).as_bytes()) {
+        eprintln!("Could not respond: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_diagnostic() {
+        assert_eq!(json_diagnostic("[1, 2]"), None);
+        // Documents that end too early are shown at their end
+        assert_eq!(
+            json_diagnostic("{\"a\": [1, 2").unwrap(),
+            "error: expected more input\n --> line 1, column 12\n  |\n1 | {\"a\": [1, 2\n  |            ^"
+        );
+        assert_eq!(
+            json_diagnostic("[\n  \"abc").unwrap(),
+            "error: expected more input\n --> line 2, column 7\n  |\n2 |   \"abc\n  |       ^"
+        );
     }
 }
@@ -0,0 +1,782 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use super::comb::{either, map, map_result, map_result_halts, optional, recognize, ret};
+use super::error::{Error, Expected};
+use super::repeat::{repeat_any, repeat_some};
+use super::sequence::{between, preceded, serial, terminated};
+use super::str::{
+    alpha_char, char, char_ci, char_of, eof, literal, literal_ci, take_some_while, take_while,
+};
+use super::tools::accept_limit;
+use super::Parse;
+use crate::{either, serial};
+
+// Grammars written in ABNF, as in RFC 5234 with the case-sensitive strings of RFC 7405
+//
+// Grammars are loaded at runtime, and their rules are matched with backtracking, so rules can be
+// written as in the RFCs, like `dec-octet` that lists `DIGIT` before the longer alternatives.
+// This is slower than the handwritten parsers, and meant for checking them against the RFCs.
+
+/// Core rules of RFC 5234 appendix B.1, which every grammar can use
+const CORE_RULES: &str = "\
+ALPHA = %x41-5A / %x61-7A
+BIT = \"0\" / \"1\"
+CHAR = %x01-7F
+CR = %x0D
+CRLF = CR LF
+CTL = %x00-1F / %x7F
+DIGIT = %x30-39
+DQUOTE = %x22
+HEXDIG = DIGIT / \"A\" / \"B\" / \"C\" / \"D\" / \"E\" / \"F\"
+HTAB = %x09
+LF = %x0A
+LWSP = *(WSP / CRLF WSP)
+OCTET = %x00-FF
+SP = %x20
+VCHAR = %x21-7E
+WSP = SP / HTAB
+";
+
+/// An element of a rule definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Alternation(Vec<Node>),
+    Concatenation(Vec<Node>),
+    /// At least and at most this many repetitions
+    Repetition(usize, Option<usize>, Box<Node>),
+    /// A reference to a rule, by its lowercase name
+    Rule(String),
+    /// A quoted string, and whether it is case-sensitive
+    String(String, bool),
+    /// A character from the first to the last code point, inclusive
+    Range(u32, u32),
+    /// A prose description, which never matches
+    Prose(String),
+}
+
+/// A set of rules, looked up by name ignoring case
+#[derive(Debug, Clone, Default)]
+pub struct Grammar {
+    /// The rules by lowercase name, along with their name as defined
+    rules: HashMap<String, (String, Node)>,
+}
+
+impl FromStr for Grammar {
+    type Err = Error;
+
+    /// Parses the rules of an ABNF text, and adds the core rules it doesn't define
+    /// Lines may end with CRLF or just LF
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let mut grammar = Grammar::default();
+        grammar.load(text)?;
+        let mut core = Grammar::default();
+        core.load(CORE_RULES).expect("the core rules are valid");
+        for (key, rule) in core.rules {
+            grammar.rules.entry(key).or_insert(rule);
+        }
+        Ok(grammar)
+    }
+}
+
+impl Grammar {
+    fn load(&mut self, text: &str) -> Result<(), Error> {
+        let mut rest = text;
+        loop {
+            rest = blank_lines(rest).finish()?.1;
+            if rest.trim_start_matches([' ', '\t']).is_empty() {
+                return Ok(());
+            }
+            let ((name, incremental, node), sur) = rule(rest).finish()?;
+            let key = name.to_ascii_lowercase();
+            match (self.rules.get_mut(&key), incremental) {
+                (None, false) => {
+                    self.rules.insert(key, (name.to_string(), node));
+                }
+                (Some((_, defined)), true) => {
+                    let mut alternatives =
+                        match std::mem::replace(defined, Node::Prose(String::new())) {
+                            Node::Alternation(alternatives) => alternatives,
+                            defined => vec![defined],
+                        };
+                    match node {
+                        Node::Alternation(more) => alternatives.extend(more),
+                        node => alternatives.push(node),
+                    }
+                    *defined = Node::Alternation(alternatives);
+                }
+                (Some(_), false) => {
+                    return Err(Error::new(
+                        &rest,
+                        Expected::Label("rule not already defined"),
+                    ))
+                }
+                (None, true) => return Err(Error::new(&rest, Expected::Label("defined rule"))),
+            }
+            rest = sur;
+        }
+    }
+
+    /// The definition of a rule
+    pub fn rule(&self, name: &str) -> Option<&Node> {
+        self.rules
+            .get(&name.to_ascii_lowercase())
+            .map(|(_, node)| node)
+    }
+
+    /// The rules by lowercase name
+    pub fn rules(&self) -> impl Iterator<Item = (&str, &Node)> {
+        self.rules
+            .iter()
+            .map(|(key, (_, node))| (key.as_str(), node))
+    }
+
+    /// Rules that are referred to but not defined, sorted
+    pub fn undefined(&self) -> Vec<&str> {
+        fn walk<'g>(grammar: &'g Grammar, node: &'g Node, found: &mut Vec<&'g str>) {
+            match node {
+                Node::Alternation(nodes) | Node::Concatenation(nodes) => {
+                    nodes.iter().for_each(|n| walk(grammar, n, found))
+                }
+                Node::Repetition(_, _, node) => walk(grammar, node, found),
+                Node::Rule(name) if !grammar.rules.contains_key(name) => found.push(name),
+                _ => (),
+            }
+        }
+        let mut found = Vec::new();
+        for (_, node) in self.rules.values() {
+            walk(self, node, &mut found);
+        }
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    /// A parser of the rule, which returns the syntax tree of the longest match
+    ///
+    /// Limits if more input could change the match, like the handwritten parsers.
+    /// Values are matched against characters, so `%x80-FF` matches Latin-1 and not bytes.
+    pub fn parser<'g>(
+        &'g self,
+        rule: &str,
+    ) -> impl for<'i> Fn(&'i str) -> Parse<&'i str, Tree<'g, 'i>> + 'g {
+        let root = Node::Rule(rule.to_ascii_lowercase());
+        move |i: &str| {
+            let mut matcher = Matcher::new(self, i);
+            let mut ends = matcher.ends(&root, 0);
+            ends.sort_by_key(|(end, _)| *end);
+            match (ends.pop(), matcher.starved) {
+                (Some((end, mut trees)), false) => Parse::Success(trees.remove(0), &i[end..]),
+                (Some((end, mut trees)), true) => Parse::Limit(Some(trees.remove(0)), &i[end..]),
+                (None, true) => Parse::Limit(None, i),
+                (None, false) => Parse::Retreat(matcher.error()),
+            }
+        }
+    }
+
+    /// Whether the rule matches all of the input
+    pub fn matches(&self, rule: &str, input: &str) -> bool {
+        let root = Node::Rule(rule.to_ascii_lowercase());
+        Matcher::new(self, input)
+            .ends(&root, 0)
+            .iter()
+            .any(|(end, _)| *end == input.len())
+    }
+}
+
+/// A rule matched by a grammar, and the rules matched within it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tree<'g, 'i> {
+    rule: &'g str,
+    text: &'i str,
+    children: Vec<Tree<'g, 'i>>,
+}
+
+impl<'g, 'i> Tree<'g, 'i> {
+    /// The name of the rule, as defined
+    pub fn rule(&self) -> &'g str {
+        self.rule
+    }
+
+    pub fn text(&self) -> &'i str {
+        self.text
+    }
+
+    pub fn children(&self) -> &[Tree<'g, 'i>] {
+        &self.children
+    }
+
+    /// The first match of the rule within the tree, depth first
+    pub fn find(&self, rule: &str) -> Option<&Tree<'g, 'i>> {
+        if self.rule.eq_ignore_ascii_case(rule) {
+            return Some(self);
+        }
+        self.children.iter().find_map(|c| c.find(rule))
+    }
+
+    /// The matches of the rule within the tree that are not within another of its matches
+    pub fn find_all(&self, rule: &str) -> Vec<&Tree<'g, 'i>> {
+        match self.rule.eq_ignore_ascii_case(rule) {
+            true => vec![self],
+            false => self
+                .children
+                .iter()
+                .flat_map(|c| c.find_all(rule))
+                .collect(),
+        }
+    }
+}
+
+/// Where a node can end, with the trees matched on the way
+/// There is at most one entry for each end, the first one found.
+type Ends<'g, 'i> = Vec<(usize, Vec<Tree<'g, 'i>>)>;
+
+fn push_end<'g, 'i>(ends: &mut Ends<'g, 'i>, end: usize, trees: Vec<Tree<'g, 'i>>) {
+    if !ends.iter().any(|(e, _)| *e == end) {
+        ends.push((end, trees));
+    }
+}
+
+/// Finds every way a node matches from a position, remembering the matches of rules
+struct Matcher<'g, 'i> {
+    grammar: &'g Grammar,
+    input: &'i str,
+    memo: HashMap<(&'g str, usize), Vec<(usize, Tree<'g, 'i>)>>,
+    /// Rules being matched, to stop left recursion
+    active: HashSet<(&'g str, usize)>,
+    /// Whether the input ended before a node was decided
+    starved: bool,
+    furthest: usize,
+    expected: Vec<Expected>,
+}
+
+impl<'g, 'i> Matcher<'g, 'i> {
+    fn new(grammar: &'g Grammar, input: &'i str) -> Self {
+        Matcher {
+            grammar,
+            input,
+            memo: HashMap::new(),
+            active: HashSet::new(),
+            starved: false,
+            furthest: 0,
+            expected: Vec::new(),
+        }
+    }
+
+    /// Records what was expected at a position, keeping what was expected furthest in
+    fn fail(&mut self, position: usize, expected: Expected) {
+        if position > self.furthest {
+            self.furthest = position;
+            self.expected.clear();
+        }
+        if position == self.furthest && !self.expected.contains(&expected) {
+            self.expected.push(expected);
+        }
+    }
+
+    fn error(&self) -> Error {
+        let at = &self.input[self.furthest..];
+        self.expected
+            .iter()
+            .map(|e| Error::new(&at, *e))
+            .reduce(Error::merge)
+            .unwrap_or_else(|| Error::new(&at, Expected::Predicate))
+    }
+
+    fn ends(&mut self, node: &Node, start: usize) -> Ends<'g, 'i> {
+        match node {
+            Node::Alternation(nodes) => {
+                let mut ends = Vec::new();
+                for node in nodes {
+                    for (end, trees) in self.ends(node, start) {
+                        push_end(&mut ends, end, trees);
+                    }
+                }
+                ends
+            }
+            Node::Concatenation(nodes) => {
+                let mut ends = vec![(start, Vec::new())];
+                for node in nodes {
+                    let mut next = Vec::new();
+                    for (position, trees) in ends {
+                        for (end, more) in self.ends(node, position) {
+                            let mut trees = trees.clone();
+                            trees.extend(more);
+                            push_end(&mut next, end, trees);
+                        }
+                    }
+                    ends = next;
+                }
+                ends
+            }
+            Node::Repetition(min, max, node) => {
+                let mut ends = Vec::new();
+                let mut frontier = vec![(start, Vec::new())];
+                // Positions reached with enough repetitions, which need not be repeated from again
+                let mut reached = HashSet::new();
+                let mut count = 0;
+                loop {
+                    if count >= *min {
+                        frontier.retain(|(position, _)| reached.insert(*position));
+                        for (position, trees) in &frontier {
+                            push_end(&mut ends, *position, trees.clone());
+                        }
+                    }
+                    if frontier.is_empty() || *max == Some(count) {
+                        return ends;
+                    }
+                    let mut next = Vec::new();
+                    for (position, trees) in frontier {
+                        for (end, more) in self.ends(node, position) {
+                            let mut trees = trees.clone();
+                            trees.extend(more);
+                            push_end(&mut next, end, trees);
+                        }
+                    }
+                    frontier = next;
+                    count += 1;
+                }
+            }
+            Node::Rule(name) => self.rule(name, start),
+            Node::String(string, case_sensitive) => {
+                let mut position = start;
+                for expected in string.chars() {
+                    match self.input[position..].chars().next() {
+                        Some(c)
+                            if c == expected
+                                || (!case_sensitive && c.eq_ignore_ascii_case(&expected)) =>
+                        {
+                            position += c.len_utf8()
+                        }
+                        Some(_) => {
+                            self.fail(position, Expected::Char(expected));
+                            return Vec::new();
+                        }
+                        None => {
+                            self.starved = true;
+                            return Vec::new();
+                        }
+                    }
+                }
+                vec![(position, Vec::new())]
+            }
+            Node::Range(first, last) => match self.input[start..].chars().next() {
+                Some(c) if (*first..=*last).contains(&(c as u32)) => {
+                    vec![(start + c.len_utf8(), Vec::new())]
+                }
+                Some(_) => {
+                    let expected = match char::from_u32(*first) {
+                        Some(c) if first == last => Expected::Char(c),
+                        _ => Expected::Predicate,
+                    };
+                    self.fail(start, expected);
+                    Vec::new()
+                }
+                None => {
+                    self.starved = true;
+                    Vec::new()
+                }
+            },
+            Node::Prose(_) => {
+                self.fail(start, Expected::Predicate);
+                Vec::new()
+            }
+        }
+    }
+
+    fn rule(&mut self, name: &str, start: usize) -> Ends<'g, 'i> {
+        let Some((key, (name, node))) = self.grammar.rules.get_key_value(name) else {
+            self.fail(start, Expected::Predicate);
+            return Vec::new();
+        };
+        let key = key.as_str();
+        if let Some(ends) = self.memo.get(&(key, start)) {
+            return ends
+                .iter()
+                .map(|(end, tree)| (*end, vec![tree.clone()]))
+                .collect();
+        }
+        if !self.active.insert((key, start)) {
+            return Vec::new();
+        }
+        let ends: Vec<_> = self
+            .ends(node, start)
+            .into_iter()
+            .map(|(end, children)| {
+                let tree = Tree {
+                    rule: name,
+                    text: &self.input[start..end],
+                    children,
+                };
+                (end, tree)
+            })
+            .collect();
+        self.active.remove(&(key, start));
+        self.memo.insert((key, start), ends.clone());
+        ends.into_iter()
+            .map(|(end, tree)| (end, vec![tree]))
+            .collect()
+    }
+}
+
+// Parsers of ABNF itself, following the grammar in RFC 5234 section 4
+// The text is complete, so the parsers accept limits.
+
+fn wsp(i: &str) -> Parse<&str, char> {
+    accept_limit(char_of(" \t"))(i)
+}
+
+fn newline(i: &str) -> Parse<&str, &str> {
+    accept_limit(either(literal("\r\n"), literal("\n")))(i)
+}
+
+fn comment(i: &str) -> Parse<&str, &str> {
+    preceded(
+        accept_limit(char(';')),
+        accept_limit(take_while(|c: char| c != '\r' && c != '\n')),
+    )(i)
+}
+
+/// A comment or a line ending, where the last line may end without one
+fn c_nl(i: &str) -> Parse<&str, ()> {
+    either(
+        map(
+            serial(comment, either(ret(newline, ()), accept_limit(eof))),
+            |_| (),
+        ),
+        ret(newline, ()),
+    )(i)
+}
+
+/// Whitespace, which may continue on the next line
+fn c_wsp(i: &str) -> Parse<&str, char> {
+    either(wsp, preceded(c_nl, wsp))(i)
+}
+
+fn blank_lines(i: &str) -> Parse<&str, Vec<()>> {
+    repeat_any(preceded(repeat_any(wsp), c_nl))(i)
+}
+
+fn rule(i: &str) -> Parse<&str, (&str, bool, Node)> {
+    map(
+        serial!(rulename, defined_as, elements, c_nl),
+        |(name, incremental, node, _)| (name, incremental, node),
+    )(i)
+}
+
+fn rulename(i: &str) -> Parse<&str, &str> {
+    accept_limit(recognize(serial(
+        alpha_char,
+        take_while(|c: char| c.is_ascii_alphanumeric() || c == '-'),
+    )))(i)
+}
+
+/// `=` for a new rule, or `=/` for alternatives to a defined rule
+fn defined_as(i: &str) -> Parse<&str, bool> {
+    between(
+        repeat_any(c_wsp),
+        either(
+            ret(accept_limit(literal("=/")), true),
+            ret(accept_limit(char('=')), false),
+        ),
+        repeat_any(c_wsp),
+    )(i)
+}
+
+fn elements(i: &str) -> Parse<&str, Node> {
+    terminated(alternation, repeat_any(c_wsp))(i)
+}
+
+fn alternation(i: &str) -> Parse<&str, Node> {
+    let slash = serial!(
+        repeat_any(c_wsp),
+        accept_limit(char('/')),
+        repeat_any(c_wsp)
+    );
+    map(
+        serial(concatenation, repeat_any(preceded(slash, concatenation))),
+        |(first, rest)| match rest.is_empty() {
+            true => first,
+            false => Node::Alternation([vec![first], rest].concat()),
+        },
+    )(i)
+}
+
+fn concatenation(i: &str) -> Parse<&str, Node> {
+    map(
+        serial(
+            repetition,
+            repeat_any(preceded(repeat_some(c_wsp), repetition)),
+        ),
+        |(first, rest)| match rest.is_empty() {
+            true => first,
+            false => Node::Concatenation([vec![first], rest].concat()),
+        },
+    )(i)
+}
+
+fn repetition(i: &str) -> Parse<&str, Node> {
+    map(
+        serial(optional(repeat), element),
+        |(repeat, element)| match repeat {
+            Some((min, max)) => Node::Repetition(min, max, Box::new(element)),
+            None => element,
+        },
+    )(i)
+}
+
+/// `n`, `n*m`, `n*`, `*m` or `*`
+/// A range whose bounds are out of order halts, rather than being parsed as a count.
+fn repeat(i: &str) -> Parse<&str, (usize, Option<usize>)> {
+    either(
+        map_result_halts(
+            serial!(optional(count), accept_limit(char('*')), optional(count)),
+            |(min, _, max)| match (min.unwrap_or(0), max) {
+                (min, Some(max)) if max < min => Err(()),
+                bounds => Ok(bounds),
+            },
+            "ascending repetition range",
+        ),
+        map(count, |n| (n, Some(n))),
+    )(i)
+}
+
+fn count(i: &str) -> Parse<&str, usize> {
+    map_result(|i| number(10, i), usize::try_from, "repetition count")(i)
+}
+
+fn element(i: &str) -> Parse<&str, Node> {
+    either!(
+        map(rulename, |name| Node::Rule(name.to_ascii_lowercase())),
+        group,
+        option,
+        char_val,
+        num_val,
+        prose_val,
+    )(i)
+}
+
+fn group(i: &str) -> Parse<&str, Node> {
+    between(
+        serial(accept_limit(char('(')), repeat_any(c_wsp)),
+        alternation,
+        serial(repeat_any(c_wsp), accept_limit(char(')'))),
+    )(i)
+}
+
+fn option(i: &str) -> Parse<&str, Node> {
+    map(
+        between(
+            serial(accept_limit(char('[')), repeat_any(c_wsp)),
+            alternation,
+            serial(repeat_any(c_wsp), accept_limit(char(']'))),
+        ),
+        |node| Node::Repetition(0, Some(1), Box::new(node)),
+    )(i)
+}
+
+/// A quoted string, case-insensitive unless prefixed by `%s`
+fn char_val(i: &str) -> Parse<&str, Node> {
+    map(
+        serial(
+            optional(either(
+                ret(accept_limit(literal_ci::<&str>("%s")), true),
+                ret(accept_limit(literal_ci::<&str>("%i")), false),
+            )),
+            between(
+                accept_limit(char('"')),
+                accept_limit(take_while(|c: char| matches!(c, ' '..='!' | '#'..='~'))),
+                accept_limit(char('"')),
+            ),
+        ),
+        |(case_sensitive, string)| Node::String(string.to_string(), case_sensitive == Some(true)),
+    )(i)
+}
+
+/// `%x41`, a range like `%x41-5A`, or a concatenation like `%x0D.0A`, in binary, decimal or hex
+fn num_val(i: &str) -> Parse<&str, Node> {
+    preceded(
+        accept_limit(char('%')),
+        either!(|i| values('b', 2, i), |i| values('d', 10, i), |i| values(
+            'x', 16, i
+        )),
+    )(i)
+}
+
+fn values(base: char, radix: u32, i: &str) -> Parse<&str, Node> {
+    preceded(
+        accept_limit(char_ci(base)),
+        map_result(
+            serial!(
+                |i| number(radix, i),
+                optional(preceded(accept_limit(char('-')), |i| number(radix, i))),
+                repeat_any(preceded(accept_limit(char('.')), |i| number(radix, i))),
+            ),
+            |values| match values {
+                (first, None, rest) if rest.is_empty() => Ok(Node::Range(first, first)),
+                (first, Some(last), rest) if rest.is_empty() && first <= last => {
+                    Ok(Node::Range(first, last))
+                }
+                (first, None, rest) => Ok(Node::Concatenation(
+                    [vec![first], rest]
+                        .concat()
+                        .into_iter()
+                        .map(|v| Node::Range(v, v))
+                        .collect(),
+                )),
+                _ => Err(()),
+            },
+            "ascending value range",
+        ),
+    )(i)
+}
+
+fn number(radix: u32, i: &str) -> Parse<&str, u32> {
+    map_result(
+        accept_limit(take_some_while(move |c: char| c.is_digit(radix))),
+        move |digits: &str| u32::from_str_radix(digits, radix),
+        "number",
+    )(i)
+}
+
+fn prose_val(i: &str) -> Parse<&str, Node> {
+    map(
+        between(
+            accept_limit(char('<')),
+            accept_limit(take_while(|c: char| matches!(c, ' '..='=' | '?'..='~'))),
+            accept_limit(char('>')),
+        ),
+        |prose: &str| Node::Prose(prose.to_string()),
+    )(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAMMAR: &str = "\
+; Addresses of RFC 3986, with a comment
+IPv4address = dec-octet \".\" dec-octet \".\" dec-octet \".\" dec-octet
+dec-octet   = DIGIT                 ; 0-9
+            / %x31-39 DIGIT         ; 10-99
+            / \"1\" 2DIGIT            ; 100-199
+            / \"2\" %x30-34 DIGIT     ; 200-249
+            / \"25\" %x30-35          ; 250-255
+
+greeting = %s\"Hi\" [\",\"] 1*2SP name
+greeting =/ \"hello\" *SP name
+name = 1*ALPHA
+crlf-pair = %d13.10 <a prose line ending>
+";
+
+    #[test]
+    fn test_load() {
+        let grammar: Grammar = GRAMMAR.parse().unwrap();
+        assert_eq!(
+            grammar.rule("Greeting"),
+            Some(&Node::Alternation(vec![
+                Node::Concatenation(vec![
+                    Node::String("Hi".to_string(), true),
+                    Node::Repetition(0, Some(1), Box::new(Node::String(",".to_string(), false))),
+                    Node::Repetition(1, Some(2), Box::new(Node::Rule("sp".to_string()))),
+                    Node::Rule("name".to_string()),
+                ]),
+                Node::Concatenation(vec![
+                    Node::String("hello".to_string(), false),
+                    Node::Repetition(0, None, Box::new(Node::Rule("sp".to_string()))),
+                    Node::Rule("name".to_string()),
+                ]),
+            ]))
+        );
+        assert_eq!(
+            grammar.rule("crlf-pair"),
+            Some(&Node::Concatenation(vec![
+                Node::Concatenation(vec![Node::Range(13, 13), Node::Range(10, 10)]),
+                Node::Prose("a prose line ending".to_string()),
+            ]))
+        );
+        assert!(grammar.rule("DIGIT").is_some());
+        assert_eq!(grammar.undefined(), Vec::<&str>::new());
+        let grammar: Grammar = "a = b c\r\nc = %b1010\r\n".parse().unwrap();
+        assert_eq!(grammar.undefined(), vec!["b"]);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let input = "a = \"x\"\nb = %x39-30\n";
+        let error = input.parse::<Grammar>().unwrap_err();
+        assert_eq!(error.offset(&input), 14);
+        assert_eq!(
+            error.expected(),
+            &[Expected::Label("ascending value range")]
+        );
+
+        let input = "a = \"x\"\na = \"y\"\n";
+        let error = input.parse::<Grammar>().unwrap_err();
+        assert_eq!(error.offset(&input), 8);
+        assert!("a =/ \"x\"".parse::<Grammar>().is_err());
+        assert!("a = (\"x\"".parse::<Grammar>().is_err());
+        let input = "a = \"x\"\nb = 3*2\"y\"\n";
+        let error = input.parse::<Grammar>().unwrap_err();
+        assert_eq!(error.offset(&input), 12);
+        assert_eq!(
+            error.expected(),
+            &[Expected::Label("ascending repetition range")]
+        );
+    }
+
+    #[test]
+    fn test_repeat() {
+        assert_eq!(repeat("2*3\""), Parse::Success((2, Some(3)), "\""));
+        assert_eq!(repeat("3*3\""), Parse::Success((3, Some(3)), "\""));
+        assert_eq!(repeat("*\""), Parse::Success((0, None), "\""));
+        assert_eq!(repeat("*4\""), Parse::Success((0, Some(4)), "\""));
+        assert_eq!(repeat("1*\""), Parse::Success((1, None), "\""));
+        assert_eq!(repeat("4\""), Parse::Success((4, Some(4)), "\""));
+        assert!(repeat("3*2\"").is_halt());
+    }
+
+    #[test]
+    fn test_match() {
+        let grammar: Grammar = GRAMMAR.parse().unwrap();
+        for octet in ["0", "9", "10", "199", "249", "255"] {
+            assert!(grammar.matches("dec-octet", octet), "{octet}");
+        }
+        for octet in ["", "00", "256", "300", "1000"] {
+            assert!(!grammar.matches("dec-octet", octet), "{octet}");
+        }
+        assert!(grammar.matches("ipv4address", "255.0.10.1"));
+        assert!(grammar.matches("greeting", "Hi, Ann"));
+        assert!(grammar.matches("greeting", "HELLO Bob"));
+        assert!(!grammar.matches("greeting", "hi Ann"));
+        assert!(!grammar.matches("greeting", "Hi   Ann"));
+
+        let address = grammar.parser("IPv4address");
+        let tree = address("10.0.0.255 ").finish().unwrap().0;
+        assert_eq!((tree.rule(), tree.text()), ("IPv4address", "10.0.0.255"));
+        let octets: Vec<_> = tree.children().iter().map(|c| c.text()).collect();
+        assert_eq!(octets, vec!["10", "0", "0", "255"]);
+        // The "1" of "10" is matched by `%x31-39`
+        assert_eq!(tree.find("digit").map(Tree::text), Some("0"));
+        assert_eq!(tree.find_all("dec-octet").len(), 4);
+        assert_eq!(address("10.0.0.255 ").finish().unwrap().1, " ");
+
+        assert!(address("10.0.0.2").is_limit());
+        assert_eq!(address("10.0"), Parse::Limit(None, "10.0"));
+        let input = "10.0.x";
+        match address(input) {
+            Parse::Retreat(e) => {
+                assert_eq!(e.offset(&input), 5);
+                assert_eq!(
+                    e.expected(),
+                    &[
+                        Expected::Predicate,
+                        Expected::Char('1'),
+                        Expected::Char('2')
+                    ]
+                );
+            }
+            p => panic!("{p:?}"),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use super::comb::map;
+use super::error::{Error, Expected};
+use super::Parse;
+
+// Parsers of binary data, for protocols like WebSocket, HTTP/2 and CBOR
+//
+// The parsers limit when the input ends before a value is complete, so they can be used with
+// `Stream`. The length of a value is known from its first bytes, so a complete value never limits.
+
+/// The first `N` bytes
+fn array<const N: usize>(i: &[u8]) -> Parse<&[u8], [u8; N]> {
+    match i.split_first_chunk::<N>() {
+        Some((bytes, rest)) => Parse::Success(*bytes, rest),
+        None => Parse::Limit(None, i),
+    }
+}
+
+macro_rules! integers {
+    ($($(#[$doc:meta])* $name:ident: $t:ty = $from:ident;)*) => {
+        $(
+            $(#[$doc])*
+            pub fn $name(i: &[u8]) -> Parse<&[u8], $t> {
+                map(array, <$t>::$from)(i)
+            }
+        )*
+    };
+}
+
+// Fixed-width integers, big-endian as in network protocols, or little-endian
+integers! {
+    /// Parses one byte
+    u8: u8 = from_be_bytes;
+    /// Parses one byte as a signed number
+    i8: i8 = from_be_bytes;
+    be_u16: u16 = from_be_bytes;
+    be_u32: u32 = from_be_bytes;
+    be_u64: u64 = from_be_bytes;
+    be_i16: i16 = from_be_bytes;
+    be_i32: i32 = from_be_bytes;
+    be_i64: i64 = from_be_bytes;
+    le_u16: u16 = from_le_bytes;
+    le_u32: u32 = from_le_bytes;
+    le_u64: u64 = from_le_bytes;
+    le_i16: i16 = from_le_bytes;
+    le_i32: i32 = from_le_bytes;
+    le_i64: i64 = from_le_bytes;
+}
+
+/// Parses an unsigned LEB128 number, seven bits per byte with the least significant first
+/// Retreats if the number doesn't fit in 64 bits.
+pub fn leb128_u64(i: &[u8]) -> Parse<&[u8], u64> {
+    let mut value = 0;
+    for (index, &byte) in i.iter().enumerate() {
+        let bits = (byte & 0x7F) as u64;
+        if index == 10 || index == 9 && bits > 1 {
+            return Parse::Retreat(Error::new(&i, Expected::Label("LEB128 within 64 bits")));
+        }
+        value |= bits << (7 * index);
+        if byte & 0x80 == 0 {
+            return Parse::Success(value, &i[index + 1..]);
+        }
+    }
+    Parse::Limit(None, i)
+}
+
+/// Parses a variable-length integer of QUIC, RFC 9000 section 16
+/// The two most significant bits of the first byte give the length: 1, 2, 4 or 8 bytes.
+pub fn quic_varint(i: &[u8]) -> Parse<&[u8], u64> {
+    let Some(&first) = i.first() else {
+        return Parse::Limit(None, i);
+    };
+    let len = 1 << (first >> 6);
+    match i.get(1..len) {
+        Some(bytes) => {
+            let value = bytes
+                .iter()
+                .fold((first & 0x3F) as u64, |value, &b| value << 8 | b as u64);
+            Parse::Success(value, &i[len..])
+        }
+        None => Parse::Limit(None, i),
+    }
+}
+
+/// Parses a length with `length`, and returns that many bytes after it
+/// `length_data(be_u16)` parses a slice prefixed with its length as two bytes.
+pub fn length_data<'a, N, P>(length: P) -> impl Fn(&'a [u8]) -> Parse<&'a [u8], &'a [u8]>
+where
+    P: Fn(&'a [u8]) -> Parse<&'a [u8], N>,
+    N: TryInto<usize>,
+{
+    move |i: &'a [u8]| {
+        let (len, rest) = match length(i) {
+            Parse::Success(len, rest) => (len, rest),
+            Parse::Limit(_, _) => return Parse::Limit(None, i),
+            Parse::Retreat(r) => return Parse::Retreat(r),
+            Parse::Halt(h) => return Parse::Halt(h),
+        };
+        let Ok(len) = len.try_into() else {
+            return Parse::Retreat(Error::new(
+                &i,
+                Expected::Label("length that fits in memory"),
+            ));
+        };
+        match rest.split_at_checked(len) {
+            Some((data, rest)) => Parse::Success(data, rest),
+            None => Parse::Limit(None, i),
+        }
+    }
+}
+
+/// Input of the bit parsers, the bytes and how many bits of the first byte are taken
+/// Bits are taken from the most significant bit of a byte first, as in network protocols.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bits<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Bits<'a> {
+    /// The bytes from the one holding the next bit, for making errors in bit parsers
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The bytes after the last byte bits were taken from
+    fn after(&self) -> &'a [u8] {
+        match self.offset {
+            0 => self.bytes,
+            _ => &self.bytes[1..],
+        }
+    }
+}
+
+/// Runs a parser of bits, like flag fields, and continues after the last byte it took bits from
+pub fn bits<'a, O, P>(p: P) -> impl Fn(&'a [u8]) -> Parse<&'a [u8], O>
+where
+    P: Fn(Bits<'a>) -> Parse<Bits<'a>, O>,
+{
+    move |i: &'a [u8]| match p(Bits {
+        bytes: i,
+        offset: 0,
+    }) {
+        Parse::Success(res, sur) => Parse::Success(res, sur.after()),
+        Parse::Limit(Some(res), sur) => Parse::Limit(Some(res), sur.after()),
+        Parse::Limit(None, _) => Parse::Limit(None, i),
+        Parse::Retreat(r) => Parse::Retreat(r),
+        Parse::Halt(h) => Parse::Halt(h),
+    }
+}
+
+/// Takes `n` bits as a number, where `n` is at most 64
+pub fn take_bits<'a>(n: usize) -> impl Fn(Bits<'a>) -> Parse<Bits<'a>, u64> {
+    assert!(n <= 64, "at most 64 bits are taken at once");
+    move |i: Bits<'a>| {
+        if i.bytes.len() * 8 - i.offset < n {
+            return Parse::Limit(None, i);
+        }
+        let (mut bytes, mut offset) = (i.bytes, i.offset);
+        let mut value = 0;
+        for _ in 0..n {
+            value = value << 1 | (bytes[0] >> (7 - offset) & 1) as u64;
+            offset += 1;
+            if offset == 8 {
+                bytes = &bytes[1..];
+                offset = 0;
+            }
+        }
+        Parse::Success(value, Bits { bytes, offset })
+    }
+}
+
+/// Takes one bit as a flag
+pub fn bit(i: Bits) -> Parse<Bits, bool> {
+    map(take_bits(1), |b| b == 1)(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial;
+
+    #[test]
+    fn test_integers() {
+        let input: &[u8] = &[0x12, 0x34, 0x56, 0x78, 0x9A];
+        assert_eq!(u8(input), Parse::Success(0x12, &input[1..]));
+        assert_eq!(be_u16(input), Parse::Success(0x1234, &input[2..]));
+        assert_eq!(le_u16(input), Parse::Success(0x3412, &input[2..]));
+        assert_eq!(be_u32(input), Parse::Success(0x12345678, &input[4..]));
+        assert_eq!(
+            le_i32(&[0xFE, 0xFF, 0xFF, 0xFF]),
+            Parse::Success(-2, &[][..])
+        );
+        assert_eq!(be_u64(input), Parse::Limit(None, input));
+        assert_eq!(i8(&[0x80]), Parse::Success(-128, &[][..]));
+    }
+
+    #[test]
+    fn test_varints() {
+        assert_eq!(
+            leb128_u64(&[0xE5, 0x8E, 0x26, 1]),
+            Parse::Success(624485, &[1][..])
+        );
+        assert_eq!(leb128_u64(&[0x7F]), Parse::Success(127, &[][..]));
+        assert!(leb128_u64(&[0xE5, 0x8E]).is_limit());
+        let max = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        assert_eq!(leb128_u64(&max), Parse::Success(u64::MAX, &[][..]));
+        assert!(
+            leb128_u64(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02]).is_retreat()
+        );
+
+        // Examples of RFC 9000 appendix A.1
+        let eight = [0xC2, 0x19, 0x7C, 0x5E, 0xFF, 0x14, 0xE8, 0x8C];
+        assert_eq!(
+            quic_varint(&eight),
+            Parse::Success(151288809941952652, &[][..])
+        );
+        assert_eq!(
+            quic_varint(&[0x9D, 0x7F, 0x3E, 0x7D]),
+            Parse::Success(494878333, &[][..])
+        );
+        assert_eq!(
+            quic_varint(&[0x7B, 0xBD, 0x25]),
+            Parse::Success(15293, &[0x25][..])
+        );
+        assert_eq!(quic_varint(&[0x25]), Parse::Success(37, &[][..]));
+        assert!(quic_varint(&eight[..5]).is_limit());
+        assert!(quic_varint(&[]).is_limit());
+    }
+
+    #[test]
+    fn test_length_data() {
+        let data = length_data(be_u16);
+        assert_eq!(
+            data(&[0, 2, 7, 8, 9]),
+            Parse::Success(&[7, 8][..], &[9][..])
+        );
+        assert_eq!(data(&[0, 0]), Parse::Success(&[][..], &[][..]));
+        assert_eq!(data(&[0, 3, 7, 8]), Parse::Limit(None, &[0, 3, 7, 8][..]));
+        assert!(data(&[0]).is_limit());
+        assert_eq!(
+            length_data(quic_varint)(&[0x02, 7, 8]),
+            Parse::Success(&[7, 8][..], &[][..])
+        );
+    }
+
+    #[test]
+    fn test_bits() {
+        // The first two bytes of a masked WebSocket text frame of five bytes
+        let header = bits(serial!(bit, take_bits(3), take_bits(4), bit, take_bits(7)));
+        let frame: &[u8] = &[0x81, 0x85, 0x37];
+        assert_eq!(
+            header(frame),
+            Parse::Success((true, 0, 1, true, 5), &frame[2..])
+        );
+        assert!(header(&frame[..1]).is_limit());
+
+        // A byte that bits were taken from is skipped
+        let flags = bits(serial!(bit, bit));
+        assert_eq!(
+            flags(&[0x40, 0x01]),
+            Parse::Success((false, true), &[0x01][..])
+        );
+        assert_eq!(
+            bits(take_bits(12))(&[0xAB, 0xCD, 0xEF]),
+            Parse::Success(0xABC, &[0xEF][..])
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use super::input::ItemSet;
+
+/// Most non-ASCII ranges a class can hold
+const MAX_RANGES: usize = 32;
+const LAST_CHAR: u32 = char::MAX as u32;
+
+/// A set of characters, checked with a bitmap for ASCII and a binary search for the rest
+///
+/// Classes are built in constants from ASCII characters and ranges, and combined with set operations:
+///
+/// ```ignore
+/// const DIGIT: CharClass = CharClass::range('0', '9');
+/// const HEX: CharClass = DIGIT.union(CharClass::ascii("abcdefABCDEF")).named("hex digit");
+/// ```
+///
+/// Bytes are checked as Latin-1 characters, like `Item::as_char`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharClass {
+    ascii: u128,
+    /// Sorted, disjoint and non-adjacent inclusive ranges of non-ASCII code points
+    ranges: [(u32, u32); MAX_RANGES],
+    len: usize,
+    name: &'static str,
+}
+
+impl CharClass {
+    pub const fn empty() -> Self {
+        CharClass {
+            ascii: 0,
+            ranges: [(0, 0); MAX_RANGES],
+            len: 0,
+            name: "character",
+        }
+    }
+
+    /// The class of the characters in `chars`, which must be ASCII
+    pub const fn ascii(chars: &str) -> Self {
+        let bytes = chars.as_bytes();
+        let mut class = CharClass::empty();
+        let mut i = 0;
+        while i < bytes.len() {
+            assert!(
+                bytes[i] < 128,
+                "CharClass::ascii given a non-ASCII character"
+            );
+            class.ascii |= 1 << bytes[i];
+            i += 1;
+        }
+        class
+    }
+
+    /// The class of the characters from `first` to `last`, inclusive
+    pub const fn range(first: char, last: char) -> Self {
+        let (first, last) = (first as u32, last as u32);
+        assert!(first <= last, "CharClass::range given a reversed range");
+        let mut class = CharClass::empty();
+        let mut c = first;
+        while c <= last && c < 128 {
+            class.ascii |= 1 << c;
+            c += 1;
+        }
+        if last >= 128 {
+            class.ranges[0] = (if first < 128 { 128 } else { first }, last);
+            class.len = 1;
+        }
+        class
+    }
+
+    /// Names the class in errors
+    pub const fn named(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub const fn union(self, other: CharClass) -> Self {
+        let mut class = CharClass::empty();
+        class.ascii = self.ascii | other.ascii;
+        let (mut a, mut b) = (0, 0);
+        while a < self.len || b < other.len {
+            let next = if b == other.len || (a < self.len && self.ranges[a].0 < other.ranges[b].0) {
+                a += 1;
+                self.ranges[a - 1]
+            } else {
+                b += 1;
+                other.ranges[b - 1]
+            };
+            class = class.push(next);
+        }
+        class
+    }
+
+    /// Every character not in the class
+    pub const fn negate(self) -> Self {
+        let mut class = CharClass::empty();
+        class.ascii = !self.ascii;
+        let mut start = 128;
+        let mut i = 0;
+        while i < self.len {
+            if self.ranges[i].0 > start {
+                class = class.push((start, self.ranges[i].0 - 1));
+            }
+            start = self.ranges[i].1 + 1;
+            i += 1;
+        }
+        if start <= LAST_CHAR {
+            class = class.push((start, LAST_CHAR));
+        }
+        class
+    }
+
+    pub const fn intersection(self, other: CharClass) -> Self {
+        self.negate().union(other.negate()).negate()
+    }
+
+    /// The characters in this class but not in `other`
+    pub const fn difference(self, other: CharClass) -> Self {
+        self.intersection(other.negate())
+    }
+
+    /// Appends a range starting at or after the last one, merging them if they overlap or touch
+    const fn push(mut self, (first, last): (u32, u32)) -> Self {
+        if self.len > 0 && first <= self.ranges[self.len - 1].1 + 1 {
+            if last > self.ranges[self.len - 1].1 {
+                self.ranges[self.len - 1].1 = last;
+            }
+            return self;
+        }
+        assert!(self.len < MAX_RANGES, "CharClass has too many ranges");
+        self.ranges[self.len] = (first, last);
+        self.len += 1;
+        self
+    }
+
+    #[inline]
+    pub fn contains(&self, c: char) -> bool {
+        let c = c as u32;
+        if c < 128 {
+            return self.ascii & (1 << c) != 0;
+        }
+        self.ranges[..self.len]
+            .binary_search_by(|&(first, last)| {
+                if last < c {
+                    std::cmp::Ordering::Less
+                } else if first > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl ItemSet<char> for &CharClass {
+    fn contains_item(&self, item: char) -> bool {
+        self.contains(item)
+    }
+    fn describe(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl ItemSet<u8> for &CharClass {
+    fn contains_item(&self, item: u8) -> bool {
+        self.contains(item as char)
+    }
+    fn describe(&self) -> &'static str {
+        self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGIT: CharClass = CharClass::range('0', '9');
+    const GREEK: CharClass = CharClass::range('α', 'ω');
+    const WORD: CharClass = DIGIT
+        .union(CharClass::ascii("_"))
+        .union(GREEK)
+        .named("word");
+
+    #[test]
+    fn test_class() {
+        assert!(WORD.contains('5') && WORD.contains('_') && WORD.contains('λ'));
+        assert!(!WORD.contains('a') && !WORD.contains('Ω') && !WORD.contains('\u{7f}'));
+        assert_eq!(WORD.name(), "word");
+
+        let not_word = WORD.negate();
+        assert!(!not_word.contains('5') && !not_word.contains('λ'));
+        assert!(not_word.contains('a') && not_word.contains('Ω') && not_word.contains(char::MAX));
+        assert_eq!(not_word.negate(), WORD.named("character"));
+
+        let greek_digits = WORD.difference(CharClass::ascii("_"));
+        assert!(greek_digits.contains('1') && !greek_digits.contains('_'));
+        assert_eq!(
+            WORD.intersection(CharClass::range('\0', 'β')),
+            DIGIT
+                .union(CharClass::ascii("_"))
+                .union(CharClass::range('α', 'β'))
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let class = CharClass::range('a', '\u{100}').union(CharClass::range('\u{101}', '\u{200}'));
+        assert_eq!(class.len, 1);
+        assert!(class.contains('\u{180}') && class.contains('z'));
+        assert!(!class.contains('\u{201}'));
+        assert!((&class).contains_item(b'z'));
+        assert!(CharClass::range('\u{80}', char::MAX)
+            .negate()
+            .contains('\u{7f}'));
+        assert_eq!(CharClass::range('\u{80}', char::MAX).negate().len, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "reversed range")]
+    fn test_reversed_range() {
+        CharClass::range('z', 'a');
+    }
+}
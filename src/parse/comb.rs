@@ -1,20 +1,51 @@
-use std::fmt::Debug;
-use std::fmt::Display;
+use std::ops::Range;
 
+use super::error::{Error, Expected};
+use super::input::{Input, Located};
+use super::sequence::serial;
+use super::tools::halt;
 use super::Parse;
 
-// pub fn not<P, I, O>(p: P) -> impl Fn(I) -> Parse<I, ()>
-// where
-//     P: Fn(I) -> Parse<I, O>,
-//     I: Copy,
-// {
-//     move |i: I| match p(i) {
-//         Parse::Success(_, _) => Parse::Retreat("Negative was found".to_string()),
-//         Parse::Retreat(_) => Parse::Success((), i),
-//         Parse::Halt(_) => Parse::Success((), i),
-//         Parse::Deficient(d) => Parse::Deficient(d),
-//     }
-// }
+/// Succeeds without consuming if the parser retreats, and retreats if it succeeds
+/// Halts are passed on, and a limit is passed on as `Limit(None)` since the outcome is undecided.
+pub fn not<I, O, P>(p: P) -> impl Fn(I) -> Parse<I, ()>
+where
+    P: Fn(I) -> Parse<I, O>,
+    I: Input,
+{
+    move |i: I| match p(i) {
+        Parse::Success(_, _) => Parse::Retreat(Error::new(&i, Expected::Other)),
+        Parse::Retreat(_) => Parse::Success((), i),
+        Parse::Halt(h) => Parse::Halt(h),
+        Parse::Limit(_, _) => Parse::Limit(None, i),
+    }
+}
+
+/// Applies the parser without consuming any input
+pub fn peek<I, O, P>(p: P) -> impl Fn(I) -> Parse<I, O>
+where
+    P: Fn(I) -> Parse<I, O>,
+    I: Copy,
+{
+    move |i: I| match p(i) {
+        Parse::Success(res, _) => Parse::Success(res, i),
+        Parse::Retreat(r) => Parse::Retreat(r),
+        Parse::Halt(h) => Parse::Halt(h),
+        Parse::Limit(res, _) => Parse::Limit(res, i),
+    }
+}
+
+/// Commits to `next` once `p` has succeeded, so retreats of `next` become halts
+/// This is `serial(p, halt(next))`, and stops alternatives from being tried once the
+/// grammar is known to apply, giving errors from where the input is actually invalid.
+pub fn cut<I, O1, O2, P1, P2>(p: P1, next: P2) -> impl Fn(I) -> Parse<I, (O1, O2)>
+where
+    P1: Fn(I) -> Parse<I, O1>,
+    P2: Fn(I) -> Parse<I, O2>,
+    I: Copy,
+{
+    serial(p, halt(next))
+}
 
 pub fn optional<I, O, P>(p: P) -> impl Fn(I) -> Parse<I, Option<O>>
 where
@@ -31,17 +62,25 @@ where
 
 /// Returns the parse of the first parser that succeeds
 /// If a parser limits out, it will try others as well
+/// If all parsers retreat, their errors are merged, and with no parsers it retreats
 pub fn either_of<I, O, P>(parsers: &[P]) -> impl Fn(I) -> Parse<I, O> + '_
 where
     P: Fn(I) -> Parse<I, O>,
-    I: Copy,
+    I: Input,
 {
     move |input: I| {
         let mut limit: Option<(Option<O>, I)> = None;
+        let mut error: Option<Error> = None;
         for parser in parsers.iter() {
             match parser(input) {
                 s @ Parse::Success(_, _) => return s,
-                Parse::Retreat(_) => continue,
+                Parse::Retreat(e) => {
+                    error = Some(match error {
+                        Some(error) => error.merge(e),
+                        None => e,
+                    });
+                    continue;
+                }
                 h @ Parse::Halt(_) => return h,
                 Parse::Limit(r, s) => {
                     limit = Some((r, s));
@@ -49,10 +88,10 @@ where
                 }
             }
         }
-        if let Some((r, s)) = limit {
-            Parse::Limit(r, s)
-        } else {
-            Parse::Retreat("Not any of the anys".to_string())
+        match (limit, error) {
+            (Some((r, s)), _) => Parse::Limit(r, s),
+            (None, Some(e)) => Parse::Retreat(e),
+            (None, None) => Parse::Retreat(Error::new(&input, Expected::Other)),
         }
     }
 }
@@ -60,56 +99,31 @@ pub fn either<I, O, P1, P2>(p1: P1, p2: P2) -> impl Fn(I) -> Parse<I, O>
 where
     P1: Fn(I) -> Parse<I, O>,
     P2: Fn(I) -> Parse<I, O>,
-    I: Copy + PartialOrd + Display,
-    O: Debug,
+    I: Copy,
 {
     move |i: I| match p1(i) {
-        Parse::Retreat(_) => p2(i),
-        Parse::Limit(None, _) => match p2(i) {
+        Parse::Retreat(e1) => match p2(i) {
+            Parse::Retreat(e2) => Parse::Retreat(e1.merge(e2)),
+            a => a,
+        },
+        Parse::Limit(None, s) => match p2(i) {
             Parse::Success(r, s) => Parse::Limit(Some(r), s),
-            a @ _ => a,
+            Parse::Retreat(_) => Parse::Limit(None, s),
+            a => a,
         },
-        a @ _ => a,
+        a => a,
     }
 }
-pub fn either3<I, O, P1, P2, P3>(p1: P1, p2: P2, p3: P3) -> impl Fn(I) -> Parse<I, O>
-where
-    P1: Fn(I) -> Parse<I, O>,
-    P2: Fn(I) -> Parse<I, O>,
-    P3: Fn(I) -> Parse<I, O>,
-    I: Copy + PartialOrd + Display,
-    O: Debug,
-{
-    either(either(p1, p2), p3)
-}
-pub fn either4<I, O, P1, P2, P3, P4>(p1: P1, p2: P2, p3: P3, p4: P4) -> impl Fn(I) -> Parse<I, O>
-where
-    P1: Fn(I) -> Parse<I, O>,
-    P2: Fn(I) -> Parse<I, O>,
-    P3: Fn(I) -> Parse<I, O>,
-    P4: Fn(I) -> Parse<I, O>,
-    I: Copy + PartialOrd + Display,
-    O: Debug,
-{
-    either(either(p1, p2), either(p3, p4))
-}
-pub fn either5<I, O, P1, P2, P3, P4, P5>(
-    p1: P1,
-    p2: P2,
-    p3: P3,
-    p4: P4,
-    p5: P5,
-) -> impl Fn(I) -> Parse<I, O>
-where
-    P1: Fn(I) -> Parse<I, O>,
-    P2: Fn(I) -> Parse<I, O>,
-    P3: Fn(I) -> Parse<I, O>,
-    P4: Fn(I) -> Parse<I, O>,
-    P5: Fn(I) -> Parse<I, O>,
-    I: Copy + PartialOrd + Display,
-    O: Debug,
-{
-    either(either(p1, p2), either3(p3, p4, p5))
+/// Tries the parsers in order, like nested `either`s
+/// `either!(p1, p2, p3)` is `either(either(p1, p2), p3)`
+#[macro_export]
+macro_rules! either {
+    ($p:expr $(,)?) => {
+        $p
+    };
+    ($p1:expr, $p2:expr $(, $rest:expr)* $(,)?) => {
+        $crate::either!($crate::parse::comb::either($p1, $p2) $(, $rest)*)
+    };
 }
 
 // Returns a parser which pipes the output from the first parser to the second parser
@@ -149,51 +163,54 @@ where
     }
 }
 
-pub fn map_result<'l, I, O, P, F, M, E>(
+/// Returns a parser, which applies `func` to the `parser` result
+/// Retreats with `label` as the expected item if `func` fails
+pub fn map_result<I, O, P, F, M, E>(
     parser: P,
     func: F,
-    label: &'l str,
-) -> impl Fn(I) -> Parse<I, M> + '_
+    label: &'static str,
+) -> impl Fn(I) -> Parse<I, M>
 where
-    P: Fn(I) -> Parse<I, O> + 'l,
-    F: Fn(O) -> Result<M, E> + 'l,
-    I: Copy,
+    P: Fn(I) -> Parse<I, O>,
+    F: Fn(O) -> Result<M, E>,
+    I: Input,
 {
     move |i: I| match parser(i) {
         Parse::Success(res, sur) => match func(res) {
             Ok(m) => Parse::Success(m, sur),
-            Err(_) => Parse::Retreat(format!("Result error {}", label)),
+            Err(_) => Parse::Retreat(Error::new(&i, Expected::Label(label))),
         },
         Parse::Limit(Some(res), sur) => match func(res) {
             Ok(m) => Parse::Limit(Some(m), sur),
             Err(_) => Parse::Limit(None, sur),
         },
         Parse::Limit(None, _) => Parse::Limit(None, i),
-        Parse::Retreat(_) => Parse::Retreat("Result error".to_string()),
+        Parse::Retreat(r) => Parse::Retreat(r),
         Parse::Halt(h) => Parse::Halt(h),
     }
 }
 
-pub fn map_result_halts<'l, I, O, P, F, M, E>(
+/// Like `map_result`, but halts if `func` fails
+pub fn map_result_halts<I, O, P, F, M, E>(
     parser: P,
     func: F,
-    label: &'l str,
-) -> impl Fn(I) -> Parse<I, M> + '_
+    label: &'static str,
+) -> impl Fn(I) -> Parse<I, M>
 where
-    P: Fn(I) -> Parse<I, O> + 'l,
-    F: Fn(O) -> Result<M, E> + 'l,
-    I: Copy,
+    P: Fn(I) -> Parse<I, O>,
+    F: Fn(O) -> Result<M, E>,
+    I: Input,
 {
     move |i: I| match parser(i) {
         Parse::Success(res, sur) => match func(res) {
             Ok(m) => Parse::Success(m, sur),
-            Err(_) => Parse::Halt(format!("Result error {}", label)),
+            Err(_) => Parse::Halt(Error::new(&i, Expected::Label(label))),
         },
         Parse::Limit(Some(res), sur) => match func(res) {
             Ok(m) => Parse::Limit(Some(m), sur),
             Err(_) => Parse::Limit(None, i),
         },
-        Parse::Retreat(_) => Parse::Retreat("Result error".to_string()),
+        Parse::Retreat(r) => Parse::Retreat(r),
         Parse::Halt(h) => Parse::Halt(h),
         Parse::Limit(None, _) => Parse::Limit(None, i),
     }
@@ -203,14 +220,14 @@ pub fn map_option<I, O, P, F, M>(parser: P, func: F) -> impl Fn(I) -> Parse<I, M
 where
     P: Fn(I) -> Parse<I, O>,
     F: Fn(O) -> Option<M>,
-    I: Copy,
+    I: Input,
 {
     move |i: I| match parser(i) {
         Parse::Success(res, sur) => match func(res) {
             Some(m) => Parse::Success(m, sur),
-            None => Parse::Retreat(format!("Map option error")),
+            None => Parse::Retreat(Error::new(&i, Expected::Predicate)),
         },
-        Parse::Retreat(_) => Parse::Retreat("Result error".to_string()),
+        Parse::Retreat(r) => Parse::Retreat(r),
         Parse::Halt(h) => Parse::Halt(h),
         Parse::Limit(Some(res), sur) => match func(res) {
             Some(m) => Parse::Limit(Some(m), sur),
@@ -224,14 +241,14 @@ pub fn map_bool<I, O, P, F>(parser: P, func: F) -> impl Fn(I) -> Parse<I, O>
 where
     P: Fn(I) -> Parse<I, O>,
     F: Fn(&O) -> bool,
-    I: Copy,
+    I: Input,
 {
     move |i: I| match parser(i) {
         Parse::Success(res, sur) => match func(&res) {
             true => Parse::Success(res, sur),
-            false => Parse::Retreat(format!("Map bool error")),
+            false => Parse::Retreat(Error::new(&i, Expected::Predicate)),
         },
-        Parse::Retreat(_) => Parse::Retreat("Result error".to_string()),
+        Parse::Retreat(r) => Parse::Retreat(r),
         Parse::Halt(h) => Parse::Halt(h),
         Parse::Limit(Some(r), s) => match func(&r) {
             true => Parse::Limit(Some(r), s),
@@ -256,6 +273,45 @@ where
     }
 }
 
+/// Returns the output with the byte range it was parsed from in the original input
+pub fn spanned<I, O, P>(p: P) -> impl Fn(Located<I>) -> Parse<Located<I>, (O, Range<usize>)>
+where
+    P: Fn(Located<I>) -> Parse<Located<I>, O>,
+    I: Input,
+{
+    move |i: Located<I>| match p(i) {
+        Parse::Success(res, sur) => Parse::Success((res, i.offset()..sur.offset()), sur),
+        Parse::Retreat(r) => Parse::Retreat(r),
+        Parse::Halt(h) => Parse::Halt(h),
+        Parse::Limit(Some(res), sur) => Parse::Limit(Some((res, i.offset()..sur.offset())), sur),
+        Parse::Limit(None, _) => Parse::Limit(None, i),
+    }
+}
+
+/// Returns the input consumed by the parser instead of its output
+pub fn recognize<I, O, P>(p: P) -> impl Fn(I) -> Parse<I, I>
+where
+    P: Fn(I) -> Parse<I, O>,
+    I: Input,
+{
+    move |i: I| {
+        let consumed = |sur: I| i.split_input(i.input_len() - sur.input_len()).0;
+        match p(i) {
+            Parse::Success(_, sur) => Parse::Success(consumed(sur), sur),
+            Parse::Retreat(r) => Parse::Retreat(r),
+            Parse::Halt(h) => Parse::Halt(h),
+            Parse::Limit(Some(_), sur) => Parse::Limit(Some(consumed(sur)), sur),
+            Parse::Limit(None, _) => Parse::Limit(None, i),
+        }
+    }
+}
+
+/// Returns the input at the current position, without consuming any of it
+/// Keeps where something starts, for errors found after it is parsed.
+pub fn position<I: Copy>(i: I) -> Parse<I, I> {
+    Parse::Success(i, i)
+}
+
 // If parser succeeds,
 // pub fn resolve<P>(parser: P, value: V) {
 // }
@@ -296,5 +352,133 @@ mod tests {
             either(literal("hallo"), some_chars_of("ab"))("abab"),
             Parse::Limit(Some("abab"), "")
         );
+        assert_eq!(
+            either(literal("hallo"), some_chars_of("ab"))("hal"),
+            Parse::Limit(None, "hal")
+        );
+    }
+
+    #[test]
+    fn test_either_of() {
+        use self::str::literal;
+        let parsers = [literal("hei"), literal("hallo")];
+        assert_eq!(either_of(&parsers)("hallo!"), Parse::Success("hallo", "!"));
+        assert!(either_of(&parsers)("hi").is_retreat());
+        assert!(either_of(&parsers[..0])("hei").is_retreat());
+    }
+
+    #[test]
+    fn test_either_macro() {
+        use self::str::{literal, some_chars_of};
+        let greeting = || either!(literal("hei"), literal("hallo"), literal("heisann"));
+        assert_eq!(greeting()("hei"), Parse::Success("hei", ""));
+        assert_eq!(greeting()("hallo"), Parse::Success("hallo", ""));
+        assert!(greeting()("hade").is_retreat());
+        assert_eq!(
+            either!(literal("x"), some_chars_of("ab"), literal("hallo"))("hallo"),
+            Parse::Success("hallo", "")
+        );
+        assert_eq!(
+            either!(literal("x"), some_chars_of("ab"), literal("hallo"))("ababs"),
+            Parse::Success("abab", "s")
+        );
+        assert_eq!(
+            either!(literal("x"), literal("hallo"), some_chars_of("ab"))("abab"),
+            Parse::Limit(Some("abab"), "")
+        );
+        assert_eq!(
+            either!(literal("hallo"), literal("x"), some_chars_of("ab"))("hal"),
+            Parse::Limit(None, "hal")
+        );
+        assert_eq!(
+            either!(
+                literal("a"),
+                literal("b"),
+                literal("c"),
+                literal("d"),
+                literal("e"),
+                literal("f"),
+                literal("g"),
+            )("g"),
+            Parse::Success("g", "")
+        );
+        match either!(literal("a"), literal("b"), literal("c"))("d") {
+            Parse::Retreat(e) => assert_eq!(
+                e.expected(),
+                &[
+                    Expected::Literal("a"),
+                    Expected::Literal("b"),
+                    Expected::Literal("c")
+                ]
+            ),
+            p => panic!("{p:?}"),
+        }
+    }
+
+    #[test]
+    fn test_spanned() {
+        use self::sequence::{serial, terminated};
+        use self::str::{char, take_some_while};
+        let word = || spanned(take_some_while(|c: char| c.is_alphabetic()));
+        let input = Located::new("ab=cd;");
+        match serial(terminated(word(), char('=')), word())(input) {
+            Parse::Success(((a, a_span), (b, b_span)), rest) => {
+                assert_eq!((*a.fragment(), a_span), ("ab", 0..2));
+                assert_eq!((*b.fragment(), b_span), ("cd", 3..5));
+                assert_eq!(rest.offset(), 5);
+            }
+            p => panic!("{p:?}"),
+        }
+        match word()(Located::new("åb")) {
+            Parse::Limit(Some((_, span)), _) => assert_eq!(span, 0..3),
+            p => panic!("{p:?}"),
+        }
+    }
+
+    #[test]
+    fn test_recognize() {
+        use self::sequence::serial;
+        use self::str::{char, digit};
+        let pair = recognize(serial(digit, serial(char('.'), digit)));
+        assert_eq!(pair("1.2x"), Parse::Success("1.2", "x"));
+        assert_eq!(pair("1."), Parse::Limit(None, "1."));
+        assert!(pair("1,2").is_retreat());
+    }
+
+    #[test]
+    fn test_position() {
+        use self::sequence::serial;
+        use self::str::{char, digit};
+        let input = "a1";
+        match serial(char('a'), serial(position, digit))(input) {
+            Parse::Success((_, (start, _)), "") => assert_eq!(start, &input[1..]),
+            p => panic!("{p:?}"),
+        }
+    }
+
+    #[test]
+    fn test_lookahead() {
+        use self::str::{char, literal};
+        let not_a = not(char('a'));
+        assert_eq!(not_a("b"), Parse::Success((), "b"));
+        assert!(not_a("a").is_retreat());
+        assert_eq!(not_a(""), Parse::Limit(None, ""));
+
+        let peek_ab = peek(literal("ab"));
+        assert_eq!(peek_ab("abc"), Parse::Success("ab", "abc"));
+        assert_eq!(peek_ab("a"), Parse::Limit(None, "a"));
+        assert!(peek_ab("b").is_retreat());
+    }
+
+    #[test]
+    fn test_cut() {
+        use self::str::{char, digit, literal};
+        let entry = || cut(literal("#"), digit);
+        let line = either(entry(), map(literal("#!"), |s| (s, 0)));
+        assert_eq!(line("#1"), Parse::Success(("#", 1), ""));
+        assert!(line("#!").is_halt());
+        assert!(entry()("1").is_retreat());
+        assert_eq!(entry()("#"), Parse::Limit(None, "#"));
+        assert!(cut(char('a'), char('b'))("ac").is_halt());
     }
 }
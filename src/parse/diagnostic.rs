@@ -0,0 +1,188 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+
+use super::error::Error;
+use super::input::Input;
+
+// Diagnostics, which show a person where in the input a parse failed
+//
+// The error is placed on its line with a caret under where it occurred:
+//
+// error: expected one of ']' or ',', in array
+//  --> line 2, column 7
+//   |
+// 2 |   [1, 2 3]
+//   |         ^
+//
+// Lines are counted from line feeds, and columns in characters, both from 1. A line break of
+// CR LF is left out of the line, and byte input that isn't UTF-8 is shown with replacements.
+
+/// An error placed in the input it was made from, which displays as the line it occurred on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic<'a> {
+    pub line: usize,
+    pub column: usize,
+    /// The text of the line, without the line break
+    text: Cow<'a, str>,
+    /// The text of the line before the error
+    before: Cow<'a, str>,
+    error: &'a Error,
+}
+
+impl<'a> Diagnostic<'a> {
+    /// Places `error` in `input`, the input given to the outermost parser
+    pub fn new<I: Input>(input: &'a I, error: &'a Error) -> Self {
+        let bytes = input.input_bytes();
+        let offset = error.offset(input).min(bytes.len());
+        let start = bytes[..offset]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let end = bytes[offset..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(bytes.len(), |i| offset + i);
+        let end = match bytes[start..end].ends_with(b"\r") {
+            true => end - 1,
+            false => end,
+        };
+        let before = String::from_utf8_lossy(&bytes[start..offset.min(end)]);
+        Diagnostic {
+            line: bytes[..start].iter().filter(|&&b| b == b'\n').count() + 1,
+            column: before.chars().count() + 1,
+            text: String::from_utf8_lossy(&bytes[start..end]),
+            before,
+            error,
+        }
+    }
+}
+
+impl Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected = self.error.expected();
+        write!(f, "error: expected ")?;
+        if expected.len() > 1 {
+            write!(f, "one of ")?;
+        }
+        for (i, e) in expected.iter().enumerate() {
+            match i {
+                0 => write!(f, "{e}")?,
+                i if i + 1 == expected.len() => write!(f, " or {e}")?,
+                _ => write!(f, ", {e}")?,
+            }
+        }
+        // Runs of the same context, like arrays in arrays, are shown once with a count
+        let context = self.error.context();
+        let mut i = 0;
+        while i < context.len() {
+            let run = context[i..]
+                .iter()
+                .take_while(|&c| *c == context[i])
+                .count();
+            match run {
+                1 => write!(f, ", in {}", context[i])?,
+                run => write!(f, ", in {} ({run} deep)", context[i])?,
+            }
+            i += run;
+        }
+        writeln!(f)?;
+
+        // The gutter is as wide as the line number
+        let line = self.line.to_string();
+        let gutter = " ".repeat(line.len());
+        writeln!(f, "{gutter}--> line {line}, column {}", self.column)?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line} | {}", self.text)?;
+        // Tabs are kept, so the caret lines up with the text
+        let indent: String = self
+            .before
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "{gutter} | {indent}^")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::error::Expected;
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let input = "[\n  1,\n  tru\n]";
+        let error = Error::new(&&input[9..], Expected::Char(']'));
+        let diagnostic = Diagnostic::new(&input, &error);
+        assert_eq!((diagnostic.line, diagnostic.column), (3, 3));
+
+        // Columns count characters, and an error at the end is after the last one
+        let input = "\"æøå";
+        let error = Error::new(&&input[input.len()..], Expected::Char('"'));
+        let diagnostic = Diagnostic::new(&input, &error);
+        assert_eq!((diagnostic.line, diagnostic.column), (1, 5));
+    }
+
+    #[test]
+    fn test_display() {
+        let input = "[\n  [1, 2 3]\n]";
+        let error = Error::new(&&input[10..], Expected::Char(']'))
+            .merge(Error::new(&&input[10..], Expected::Char(',')))
+            .label(&&input[3..], "array");
+        assert_eq!(
+            Diagnostic::new(&input, &error).to_string(),
+            "\
+error: expected one of ']' or ',', in array
+ --> line 2, column 9
+  |
+2 |   [1, 2 3]
+  |         ^"
+        );
+
+        // Line breaks of CR LF are left out, and tabs are kept before the caret
+        let input: &[u8] = b"GET / HTTP/1.1\r\nHost:\texample.com\r\n\r\n";
+        let error = Error::new(&&input[22..], Expected::Label("header value"));
+        assert_eq!(
+            Diagnostic::new(&input, &error).to_string(),
+            "error: expected header value\n --> line 2, column 7\n  |\n2 | Host:\texample.com\n  |      \t^"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let diagnose = |input: &str| {
+            let error = input.parse::<crate::json::JSON>().unwrap_err();
+            Diagnostic::new(&input, &error).to_string()
+        };
+        assert_eq!(
+            diagnose(r#"{"a": [1, 2 3]}"#),
+            "\
+error: expected one of ',' or ']', in array, in object
+ --> line 1, column 13
+  |
+1 | {\"a\": [1, 2 3]}
+  |             ^"
+        );
+        assert_eq!(
+            diagnose("[\n  {\"a\" 1}\n]"),
+            "\
+error: expected ':', in object, in array
+ --> line 2, column 8
+  |
+2 |   {\"a\" 1}
+  |        ^"
+        );
+
+        // Contexts that repeat, like arrays in arrays, are named once with how deep they are
+        let header = diagnose("[[[1 2]]]").lines().next().unwrap().to_string();
+        assert_eq!(
+            header,
+            "error: expected one of ',' or ']', in array (3 deep)"
+        );
+
+        // Documents that end too early are shown at their end
+        for input in ["[1,2", "\"abc", "{\"a\": [1, 2"] {
+            let position = format!(" --> line 1, column {}", input.chars().count() + 1);
+            assert_eq!(diagnose(input).lines().nth(1), Some(position.as_str()));
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt::{self, Debug, Display};
+
+use super::input::Input;
+
+/// Something the parser expected to find where it failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+    Char(char),
+    Literal(&'static str),
+    /// Any of the characters
+    OneOf(&'static str),
+    /// Any character except these
+    NoneOf(&'static str),
+    /// A named grammar rule, as given to `tools::label`
+    Label(&'static str),
+    /// An item satisfying a predicate
+    Predicate,
+    /// More input, for when a parser that cannot end on a limit does
+    More,
+    /// The end of the input, see `str::eof`
+    End,
+    /// Anything but what a `comb::not` parser matched
+    Other,
+    /// No more than this many repetitions, see `repeat::repeat_range`
+    AtMost(usize),
+}
+
+impl Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Char(c) => write!(f, "{c:?}"),
+            Expected::Literal(l) => write!(f, "{l:?}"),
+            Expected::OneOf(s) => write!(f, "one of {s:?}"),
+            Expected::NoneOf(s) => write!(f, "anything but {s:?}"),
+            Expected::Label(l) => write!(f, "{l}"),
+            Expected::Predicate => write!(f, "a matching character"),
+            Expected::More => write!(f, "more input"),
+            Expected::End => write!(f, "end of input"),
+            Expected::Other => write!(f, "something else"),
+            Expected::AtMost(n) => write!(f, "at most {n} repetitions"),
+        }
+    }
+}
+
+/// A list that keeps its first item inline, and only allocates for more
+/// Most errors expect one item within one label, so they don't allocate.
+#[derive(Clone)]
+struct List<T> {
+    first: Option<T>,
+    /// All the items, once there is more than one
+    /// Boxed, so errors stay small for the parses they are returned in.
+    #[allow(clippy::box_collection)]
+    spilled: Option<Box<Vec<T>>>,
+}
+
+impl<T: Copy> List<T> {
+    fn empty() -> Self {
+        List {
+            first: None,
+            spilled: None,
+        }
+    }
+
+    fn one(item: T) -> Self {
+        List {
+            first: Some(item),
+            spilled: None,
+        }
+    }
+
+    fn push(&mut self, item: T) {
+        match (&mut self.spilled, self.first) {
+            (Some(spilled), _) => spilled.push(item),
+            (None, Some(first)) => self.spilled = Some(Box::new(vec![first, item])),
+            (None, None) => self.first = Some(item),
+        }
+    }
+
+    fn as_slice(&self) -> &[T] {
+        match &self.spilled {
+            Some(spilled) => spilled,
+            None => self.first.as_slice(),
+        }
+    }
+}
+
+impl<T: Copy + Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+impl<T: Copy + PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Copy + Eq> Eq for List<T> {}
+
+/// The reason a parser retreated or halted
+///
+/// The position is stored as the length of the input that remained when the parser failed,
+/// since parsers only see the input from where they start.
+/// Use `Error::offset` to get the position in the original input.
+///
+/// Errors are made for every alternative that fails, and are mostly dropped when another
+/// succeeds, so they hold static descriptions without allocating. The message is only made
+/// when an error is displayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    remaining: usize,
+    expected: List<Expected>,
+    context: List<&'static str>,
+}
+
+impl Error {
+    /// An error at the start of `input`
+    pub fn new<I: Input>(input: &I, expected: Expected) -> Self {
+        Error {
+            remaining: input.input_len(),
+            expected: List::one(expected),
+            context: List::empty(),
+        }
+    }
+
+    /// Byte offset of the error in `original`, the input given to the outermost parser
+    pub fn offset<I: Input>(&self, original: &I) -> usize {
+        original.input_len().saturating_sub(self.remaining)
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    pub fn expected(&self) -> &[Expected] {
+        self.expected.as_slice()
+    }
+
+    /// The labels the error was raised within, innermost first
+    pub fn context(&self) -> &[&'static str] {
+        self.context.as_slice()
+    }
+
+    /// Combines the errors of two alternatives
+    /// The error that got furthest wins, and if they got equally far, the expected items are merged
+    pub fn merge(mut self, other: Error) -> Self {
+        if other.remaining < self.remaining {
+            return other;
+        }
+        if other.remaining == self.remaining {
+            for &e in other.expected() {
+                if !self.expected().contains(&e) {
+                    self.expected.push(e);
+                }
+            }
+        }
+        self
+    }
+
+    /// Adds a label to the context stack
+    /// If the error is at `input`, meaning nothing was consumed, the label replaces what was expected
+    pub fn label<I: Input>(mut self, input: &I, label: &'static str) -> Self {
+        if self.remaining == input.input_len() {
+            self.expected = List::one(Expected::Label(label));
+        }
+        self.context.push(label);
+        self
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected ")?;
+        let expected = self.expected();
+        for (i, e) in expected.iter().enumerate() {
+            match i {
+                0 => write!(f, "{e}")?,
+                i if i + 1 == expected.len() => write!(f, " or {e}")?,
+                _ => write!(f, ", {e}")?,
+            }
+        }
+        for c in self.context() {
+            write!(f, ", in {c}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_merge() {
+        let input = "abc";
+        let a = Error::new(&input, Expected::Char('x'));
+        let b = Error::new(&input, Expected::Literal("yz"));
+        let further = Error::new(&&input[1..], Expected::Char('q'));
+        assert_eq!(
+            a.clone().merge(b).expected(),
+            &[Expected::Char('x'), Expected::Literal("yz")]
+        );
+        assert_eq!(a.merge(further.clone()), further);
+        assert_eq!(further.offset(&input), 1);
+    }
+
+    #[test]
+    fn test_list() {
+        let mut list = List::empty();
+        assert_eq!(list.as_slice(), &[] as &[u8]);
+        list.push(1);
+        assert!(list.spilled.is_none());
+        list.push(2);
+        list.push(3);
+        assert_eq!(list.as_slice(), &[1, 2, 3]);
+        assert_ne!(list, List::one(1));
+        assert!(std::mem::size_of::<Error>() <= 64);
+    }
+    #[test]
+    fn test_display() {
+        let input = "abc";
+        let e = Error::new(&input, Expected::Char('x'))
+            .merge(Error::new(&input, Expected::Literal("yz")))
+            .merge(Error::new(&input, Expected::Label("number")))
+            .label(&&input[1..], "value");
+        assert_eq!(e.to_string(), "expected 'x', \"yz\" or number, in value");
+    }
+}
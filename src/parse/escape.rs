@@ -0,0 +1,165 @@
+use std::borrow::Cow;
+
+use super::Parse;
+
+// Text with escapes, like JSON strings and percent-encoded URLs
+//
+// The text is runs of `normal` characters and escapes, which start with the escape character
+// followed by what `escape` parses. `normal` must not match the escape character, and must
+// consume something when it succeeds. The text ends before anything that is neither, so it may
+// be empty. An escape character followed by an invalid escape halts.
+
+/// A part of escaped text
+enum Part<'a, O> {
+    Text(&'a str),
+    Escape(O),
+}
+
+/// Runs over the escaped text, giving each part to `each`
+fn parts<'a, O1, O2, N, E>(
+    i: &'a str,
+    normal: &N,
+    escape_char: char,
+    escape: &E,
+    mut each: impl FnMut(Part<'a, O2>),
+) -> Parse<&'a str, ()>
+where
+    N: Fn(&'a str) -> Parse<&'a str, O1>,
+    E: Fn(&'a str) -> Parse<&'a str, O2>,
+{
+    let mut rest = i;
+    loop {
+        match normal(rest) {
+            Parse::Success(_, sur) => {
+                each(Part::Text(&rest[..rest.len() - sur.len()]));
+                rest = sur;
+                continue;
+            }
+            Parse::Limit(Some(_), sur) => {
+                each(Part::Text(&rest[..rest.len() - sur.len()]));
+                return Parse::Limit(Some(()), sur);
+            }
+            Parse::Limit(None, _) if !rest.is_empty() => return Parse::Limit(None, i),
+            Parse::Halt(h) => return Parse::Halt(h),
+            Parse::Retreat(_) | Parse::Limit(None, _) => (),
+        }
+        let Some(after) = rest.strip_prefix(escape_char) else {
+            return match rest.is_empty() {
+                true => Parse::Limit(Some(()), rest),
+                false => Parse::Success((), rest),
+            };
+        };
+        match escape(after) {
+            Parse::Success(res, sur) | Parse::Limit(Some(res), sur) => {
+                each(Part::Escape(res));
+                rest = sur;
+            }
+            Parse::Limit(None, _) => return Parse::Limit(None, i),
+            Parse::Retreat(e) | Parse::Halt(e) => return Parse::Halt(e),
+        }
+    }
+}
+
+/// Parses escaped text, and returns it as written
+pub fn escaped<'a, O1, O2, N, E>(
+    normal: N,
+    escape_char: char,
+    escape: E,
+) -> impl Fn(&'a str) -> Parse<&'a str, &'a str>
+where
+    N: Fn(&'a str) -> Parse<&'a str, O1>,
+    E: Fn(&'a str) -> Parse<&'a str, O2>,
+{
+    move |i: &'a str| match parts(i, &normal, escape_char, &escape, |_| ()) {
+        Parse::Success((), sur) => Parse::Success(&i[..i.len() - sur.len()], sur),
+        Parse::Limit(Some(()), sur) => Parse::Limit(Some(&i[..i.len() - sur.len()]), sur),
+        Parse::Limit(None, sur) => Parse::Limit(None, sur),
+        Parse::Retreat(r) => Parse::Retreat(r),
+        Parse::Halt(h) => Parse::Halt(h),
+    }
+}
+
+/// Parses escaped text, and returns it with the escapes replaced by what `transform` gives
+/// The text is only copied if it has escapes.
+pub fn escaped_transform<'a, O1, O2, N, T>(
+    normal: N,
+    escape_char: char,
+    transform: T,
+) -> impl Fn(&'a str) -> Parse<&'a str, Cow<'a, str>>
+where
+    N: Fn(&'a str) -> Parse<&'a str, O1>,
+    T: Fn(&'a str) -> Parse<&'a str, O2>,
+    String: Extend<O2>,
+{
+    move |i: &'a str| {
+        // Text before the first escape is borrowed
+        let mut borrowed = 0;
+        let mut owned: Option<String> = None;
+        let parsed = parts(i, &normal, escape_char, &transform, |part| {
+            match (part, &mut owned) {
+                (Part::Text(text), None) => borrowed += text.len(),
+                (Part::Text(text), Some(owned)) => owned.push_str(text),
+                (Part::Escape(res), owned) => owned
+                    .get_or_insert_with(|| i[..borrowed].to_string())
+                    .extend(Some(res)),
+            }
+        });
+        let text = || match owned {
+            Some(owned) => Cow::Owned(owned),
+            None => Cow::Borrowed(&i[..borrowed]),
+        };
+        match parsed {
+            Parse::Success((), sur) => Parse::Success(text(), sur),
+            Parse::Limit(Some(()), sur) => Parse::Limit(Some(text()), sur),
+            Parse::Limit(None, sur) => Parse::Limit(None, sur),
+            Parse::Retreat(r) => Parse::Retreat(r),
+            Parse::Halt(h) => Parse::Halt(h),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::comb::map_option;
+    use super::super::str::{char_of, pop, take_some_while};
+    use super::*;
+
+    fn word(i: &str) -> Parse<&str, &str> {
+        take_some_while(|c: char| c.is_ascii_alphabetic())(i)
+    }
+
+    fn unescape(i: &str) -> Parse<&str, char> {
+        map_option(pop, |c| match c {
+            'n' => Some('\n'),
+            '\\' => Some('\\'),
+            _ => None,
+        })(i)
+    }
+
+    #[test]
+    fn test_escaped() {
+        let text = escaped(word, '\\', char_of("n\\"));
+        assert_eq!(text(r"ab\ncd\\ e"), Parse::Success(r"ab\ncd\\", " e"));
+        assert_eq!(text(" e"), Parse::Success("", " e"));
+        assert_eq!(text(r"ab\n"), Parse::Limit(Some(r"ab\n"), ""));
+        assert_eq!(text(r"ab\"), Parse::Limit(None, r"ab\"));
+        assert!(text(r"ab\x").is_halt());
+    }
+
+    #[test]
+    fn test_escaped_transform() {
+        let text = escaped_transform(word, '\\', unescape);
+        assert_eq!(text("ab cd"), Parse::Success(Cow::Borrowed("ab"), " cd"));
+        assert!(matches!(text("ab cd"), Parse::Success(Cow::Borrowed(_), _)));
+        assert_eq!(
+            text(r"ab\ncd\\ e"),
+            Parse::Success(Cow::Owned::<str>("ab\ncd\\".to_string()), " e")
+        );
+        assert_eq!(text(r"\nab "), Parse::Success(Cow::from("\nab"), " "));
+        assert_eq!(text("ab"), Parse::Limit(Some(Cow::from("ab")), ""));
+        match text(r"ab\x") {
+            Parse::Halt(e) => assert_eq!(e.offset(&r"ab\x"), 3),
+            p => panic!("{p:?}"),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use super::abnf::{Grammar, Node};
+
+// Random inputs from ABNF grammars, for property tests of the handwritten parsers
+//
+// Inputs are generated from the rules of a grammar, and mutated to make inputs that are
+// usually invalid. The same seed always gives the same inputs, so failures can be reproduced.
+
+/// Characters that mutations insert, besides the characters of the input itself
+const MUTATION_CHARS: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@AZ[\\]^_`az{|}~é€😀";
+
+/// A small random number generator, SplitMix64, which is deterministic from its seed
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to, but not including, `n`, which must not be zero
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Generates random inputs matching the rules of a grammar
+///
+/// ```ignore
+/// let mut generator = Generator::new(&grammar, 42).max_depth(8);
+/// let input = generator.generate("JSON-text");
+/// let invalid = generator.mutate(&input);
+/// ```
+pub struct Generator<'g> {
+    grammar: &'g Grammar,
+    rng: Rng,
+    max_depth: usize,
+    max_repeat: usize,
+    /// How deep the rules must nest for each rule to match, missing if it never can
+    heights: HashMap<&'g str, usize>,
+}
+
+impl<'g> Generator<'g> {
+    pub fn new(grammar: &'g Grammar, seed: u64) -> Self {
+        let mut heights = HashMap::new();
+        loop {
+            let mut changed = false;
+            for (name, node) in grammar.rules() {
+                if let Some(height) = height(node, &heights) {
+                    if heights.get(name).is_none_or(|&h| height < h) {
+                        heights.insert(name, height);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        Generator {
+            grammar,
+            rng: Rng::new(seed),
+            max_depth: 16,
+            max_repeat: 4,
+            heights,
+        }
+    }
+
+    /// Rules nested deeper than this take their shallowest alternatives and fewest repetitions
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Most repetitions beyond the minimum, for repetitions without a maximum
+    pub fn max_repeat(mut self, repeat: usize) -> Self {
+        self.max_repeat = repeat;
+        self
+    }
+
+    /// The random number generator, for randomness that should follow the seed as well
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// A random input matching the rule
+    /// Panics if the rule is undefined, or can only match prose.
+    pub fn generate(&mut self, rule: &str) -> String {
+        let root = Node::Rule(rule.to_ascii_lowercase());
+        assert!(
+            height(&root, &self.heights).is_some(),
+            "rule {rule} can't be generated"
+        );
+        let mut output = String::new();
+        self.node(&root, self.max_depth + 1, &mut output);
+        output
+    }
+
+    /// The input with one to three random characters removed, replaced, inserted or repeated,
+    /// or cut short, which usually makes it invalid
+    pub fn mutate(&mut self, input: &str) -> String {
+        let mut chars: Vec<char> = input.chars().collect();
+        for _ in 0..1 + self.rng.below(3) {
+            let at = self.rng.below(chars.len() + 1);
+            match self.rng.below(5) {
+                0 if at < chars.len() => {
+                    chars.remove(at);
+                }
+                1 if at < chars.len() => chars[at] = self.mutation_char(&chars),
+                2 => {
+                    let len = self.rng.below(chars.len() - at + 1).min(8);
+                    let repeated = chars[at..at + len].to_vec();
+                    chars.splice(at..at, repeated);
+                }
+                3 => chars.truncate(at),
+                _ => {
+                    let c = self.mutation_char(&chars);
+                    chars.insert(at, c);
+                }
+            }
+        }
+        chars.into_iter().collect()
+    }
+
+    fn mutation_char(&mut self, chars: &[char]) -> char {
+        if !chars.is_empty() && self.rng.below(2) == 0 {
+            return chars[self.rng.below(chars.len())];
+        }
+        let count = MUTATION_CHARS.chars().count();
+        MUTATION_CHARS.chars().nth(self.rng.below(count)).unwrap()
+    }
+
+    /// Generates the node, where `depth` is how many more rules may nest
+    fn node(&mut self, node: &Node, depth: usize, output: &mut String) {
+        match node {
+            Node::Alternation(nodes) => {
+                let heights: Vec<_> = nodes.iter().map(|n| height(n, &self.heights)).collect();
+                let fitting: Vec<_> = (0..nodes.len())
+                    .filter(|&i| heights[i].is_some_and(|h| h <= depth))
+                    .collect();
+                let chosen = match fitting.is_empty() {
+                    true => (0..nodes.len())
+                        .filter(|&i| heights[i].is_some())
+                        .min_by_key(|&i| heights[i])
+                        .expect("an alternative can be generated"),
+                    false => fitting[self.rng.below(fitting.len())],
+                };
+                self.node(&nodes[chosen], depth, output)
+            }
+            Node::Concatenation(nodes) => {
+                for node in nodes {
+                    self.node(node, depth, output);
+                }
+            }
+            Node::Repetition(min, max, node) => {
+                let max = max.unwrap_or(usize::MAX).min(min + self.max_repeat);
+                let count = match height(node, &self.heights).is_some_and(|h| h <= depth) {
+                    true => min + self.rng.below(max - min + 1),
+                    false => *min,
+                };
+                for _ in 0..count {
+                    self.node(node, depth, output);
+                }
+            }
+            Node::Rule(name) => {
+                let rule = self.grammar.rule(name).expect("the rule is defined");
+                self.node(rule, depth.saturating_sub(1), output)
+            }
+            Node::String(string, case_sensitive) => {
+                for c in string.chars() {
+                    match !case_sensitive && self.rng.below(2) == 0 {
+                        true if c.is_ascii_uppercase() => output.push(c.to_ascii_lowercase()),
+                        true => output.push(c.to_ascii_uppercase()),
+                        false => output.push(c),
+                    }
+                }
+            }
+            Node::Range(first, last) => {
+                // Surrogates are not characters, so values are drawn again if they land on one
+                let c = (0..8).find_map(|_| {
+                    char::from_u32(first + self.rng.below((last - first) as usize + 1) as u32)
+                });
+                output.extend(c);
+            }
+            Node::Prose(prose) => panic!("prose <{prose}> can't be generated"),
+        }
+    }
+}
+
+/// How deep rules must nest for the node to match, or `None` if it can't
+fn height(node: &Node, heights: &HashMap<&str, usize>) -> Option<usize> {
+    match node {
+        Node::Alternation(nodes) => nodes.iter().filter_map(|n| height(n, heights)).min(),
+        Node::Concatenation(nodes) => nodes
+            .iter()
+            .map(|n| height(n, heights))
+            .try_fold(0, |max, h| Some(max.max(h?))),
+        Node::Repetition(0, _, _) => Some(0),
+        Node::Repetition(_, _, node) => height(node, heights),
+        Node::Rule(name) => heights.get(name.as_str()).map(|h| h + 1),
+        Node::String(_, _) | Node::Range(_, _) => Some(0),
+        Node::Prose(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAMMAR: &str = "\
+list = \"(\" *(list / atom) \")\"
+atom = 1*ALPHA / %x3B1-3C9 / %s\"Nil\"
+never = \"x\" never
+";
+
+    #[test]
+    fn test_generate() {
+        let grammar: Grammar = GRAMMAR.parse().unwrap();
+        let mut generator = Generator::new(&grammar, 7).max_depth(6);
+        let inputs: Vec<_> = (0..50).map(|_| generator.generate("list")).collect();
+        for input in &inputs {
+            assert!(grammar.matches("list", input), "{input}");
+            let depth = input
+                .chars()
+                .scan(0i32, |d, c| {
+                    *d += (c == '(') as i32 - (c == ')') as i32;
+                    Some(*d)
+                })
+                .max();
+            assert!(depth <= Some(7), "{input}");
+        }
+        assert!(inputs.iter().any(|i| i.contains("Nil")));
+        assert!(inputs
+            .iter()
+            .any(|i| i.chars().any(|c| ('α'..='ω').contains(&c))));
+
+        let mut again = Generator::new(&grammar, 7).max_depth(6);
+        assert_eq!(again.generate("list"), inputs[0]);
+        assert_ne!(Generator::new(&grammar, 8).generate("list"), inputs[0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be generated")]
+    fn test_generate_never() {
+        let grammar: Grammar = GRAMMAR.parse().unwrap();
+        Generator::new(&grammar, 0).generate("never");
+    }
+
+    #[test]
+    fn test_mutate() {
+        let grammar: Grammar = GRAMMAR.parse().unwrap();
+        let mut generator = Generator::new(&grammar, 1);
+        let mutated: Vec<_> = (0..50).map(|_| generator.mutate("(abc (Nil))")).collect();
+        assert!(mutated.iter().any(|m| !grammar.matches("list", m)));
+        assert!(mutated.iter().all(|m| m.len() < 40));
+        let mut again = Generator::new(&grammar, 1);
+        assert_eq!(again.mutate("(abc (Nil))"), mutated[0]);
+    }
+}
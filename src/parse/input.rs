@@ -0,0 +1,329 @@
+use std::fmt::Debug;
+use std::iter::{Copied, Enumerate};
+use std::slice::Iter;
+use std::str::CharIndices;
+
+use super::error::Expected;
+
+/// A slice-like input the parsers can consume, such as `&str` or `&[u8]`
+///
+/// Indices are always byte offsets into the input, and an input is always
+/// split on item boundaries, so the two halves are valid inputs as well.
+pub trait Input: Copy {
+    /// The element the input is made of: `char` for `&str` and `u8` for `&[u8]`
+    type Item: Item;
+    type Iter: Iterator<Item = (usize, Self::Item)>;
+
+    /// Length of the input in bytes
+    fn input_len(&self) -> usize;
+
+    /// Iterates over the items and the byte index they start at
+    fn item_indices(&self) -> Self::Iter;
+
+    /// Splits the input into `[0, index)` and `[index, len)`
+    fn split_input(&self, index: usize) -> (Self, Self);
+
+    /// The input as bytes, for scanning without decoding items
+    fn input_bytes(&self) -> &[u8];
+
+    fn first_item(&self) -> Option<Self::Item> {
+        self.item_indices().next().map(|(_, item)| item)
+    }
+
+    fn is_input_empty(&self) -> bool {
+        self.input_len() == 0
+    }
+
+    /// The empty input at the end of this input
+    fn end(&self) -> Self {
+        self.split_input(self.input_len()).1
+    }
+}
+
+/// An element of an `Input`
+pub trait Item: Copy + PartialEq + Debug {
+    /// Number of bytes the item occupies in the input
+    fn len_bytes(self) -> usize;
+
+    /// The item as a `char`, bytes are interpreted as Latin-1
+    fn as_char(self) -> char;
+
+    /// The item of an ASCII byte
+    fn from_ascii(byte: u8) -> Self;
+}
+
+impl Item for char {
+    fn len_bytes(self) -> usize {
+        self.len_utf8()
+    }
+    fn as_char(self) -> char {
+        self
+    }
+    fn from_ascii(byte: u8) -> Self {
+        byte as char
+    }
+}
+
+impl Item for u8 {
+    fn len_bytes(self) -> usize {
+        1
+    }
+    fn as_char(self) -> char {
+        self as char
+    }
+    fn from_ascii(byte: u8) -> Self {
+        byte
+    }
+}
+
+impl<'a> Input for &'a str {
+    type Item = char;
+    type Iter = CharIndices<'a>;
+
+    fn input_len(&self) -> usize {
+        self.len()
+    }
+    fn item_indices(&self) -> CharIndices<'a> {
+        self.char_indices()
+    }
+    fn split_input(&self, index: usize) -> (Self, Self) {
+        (&self[..index], &self[index..])
+    }
+    fn input_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<'a> Input for &'a [u8] {
+    type Item = u8;
+    type Iter = Enumerate<Copied<Iter<'a, u8>>>;
+
+    fn input_len(&self) -> usize {
+        self.len()
+    }
+    fn item_indices(&self) -> Self::Iter {
+        self.iter().copied().enumerate()
+    }
+    fn split_input(&self, index: usize) -> (Self, Self) {
+        (&self[..index], &self[index..])
+    }
+    fn input_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+/// A set of items, used by parsers like `other_than` and `char_of`
+///
+/// A `&str` is a set of chars, and since most delimiters are ASCII, also a set of bytes.
+pub trait ItemSet<T> {
+    fn contains_item(&self, item: T) -> bool;
+
+    /// The items of the set as text, for error messages
+    fn describe(&self) -> &'static str;
+
+    /// The bytes of the set, if it is only ASCII, so inputs can be searched bytewise
+    fn ascii_bytes(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl ItemSet<char> for &'static str {
+    fn contains_item(&self, item: char) -> bool {
+        self.contains(item)
+    }
+    fn describe(&self) -> &'static str {
+        self
+    }
+    fn ascii_bytes(&self) -> Option<&[u8]> {
+        self.is_ascii().then_some(self.as_bytes())
+    }
+}
+
+impl ItemSet<u8> for &'static str {
+    fn contains_item(&self, item: u8) -> bool {
+        self.as_bytes().contains(&item)
+    }
+    fn describe(&self) -> &'static str {
+        self
+    }
+    fn ascii_bytes(&self) -> Option<&[u8]> {
+        Some(self.as_bytes())
+    }
+}
+
+impl ItemSet<u8> for &'static [u8] {
+    fn contains_item(&self, item: u8) -> bool {
+        self.contains(&item)
+    }
+    fn describe(&self) -> &'static str {
+        std::str::from_utf8(self).unwrap_or("bytes")
+    }
+    fn ascii_bytes(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+impl<const N: usize> ItemSet<u8> for &'static [u8; N] {
+    fn contains_item(&self, item: u8) -> bool {
+        self.contains(&item)
+    }
+    fn describe(&self) -> &'static str {
+        std::str::from_utf8(self.as_slice()).unwrap_or("bytes")
+    }
+    fn ascii_bytes(&self) -> Option<&[u8]> {
+        Some(self.as_slice())
+    }
+}
+
+/// Something that can be matched against the start of an input, used by `literal`
+pub trait Tag<I> {
+    /// Returns the length of the tag in bytes if the input starts with it
+    fn match_start(&self, input: &I) -> Option<usize>;
+
+    /// Returns true if the input is a strict prefix of the tag
+    fn is_prefixed_by(&self, input: &I) -> bool;
+
+    fn expected(&self) -> Expected;
+}
+
+impl Tag<&str> for &'static str {
+    fn match_start(&self, input: &&str) -> Option<usize> {
+        input.starts_with(*self).then_some(self.len())
+    }
+    fn is_prefixed_by(&self, input: &&str) -> bool {
+        input.len() < self.len() && self.starts_with(*input)
+    }
+    fn expected(&self) -> Expected {
+        Expected::Literal(self)
+    }
+}
+
+impl Tag<&[u8]> for &'static [u8] {
+    fn match_start(&self, input: &&[u8]) -> Option<usize> {
+        input.starts_with(self).then_some(self.len())
+    }
+    fn is_prefixed_by(&self, input: &&[u8]) -> bool {
+        input.len() < self.len() && self.starts_with(input)
+    }
+    fn expected(&self) -> Expected {
+        Expected::Literal(std::str::from_utf8(self).unwrap_or("bytes"))
+    }
+}
+
+impl Tag<&[u8]> for &'static str {
+    fn match_start(&self, input: &&[u8]) -> Option<usize> {
+        self.as_bytes().match_start(input)
+    }
+    fn is_prefixed_by(&self, input: &&[u8]) -> bool {
+        self.as_bytes().is_prefixed_by(input)
+    }
+    fn expected(&self) -> Expected {
+        Expected::Literal(self)
+    }
+}
+
+impl<const N: usize> Tag<&[u8]> for &'static [u8; N] {
+    fn match_start(&self, input: &&[u8]) -> Option<usize> {
+        self.as_slice().match_start(input)
+    }
+    fn is_prefixed_by(&self, input: &&[u8]) -> bool {
+        self.as_slice().is_prefixed_by(input)
+    }
+    fn expected(&self) -> Expected {
+        Tag::<&[u8]>::expected(&self.as_slice())
+    }
+}
+
+/// An input that remembers its offset into the original input
+///
+/// Parsers run on `Located::new(input)` like on `input` itself, and `spanned` can tell
+/// where in the original input an output came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Located<I> {
+    fragment: I,
+    offset: usize,
+}
+
+impl<I> Located<I> {
+    pub fn new(input: I) -> Self {
+        Located {
+            fragment: input,
+            offset: 0,
+        }
+    }
+
+    /// The part of the original input this is
+    pub fn fragment(&self) -> &I {
+        &self.fragment
+    }
+
+    /// Byte offset of the fragment in the original input
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<I: Input> Input for Located<I> {
+    type Item = I::Item;
+    type Iter = I::Iter;
+
+    fn input_len(&self) -> usize {
+        self.fragment.input_len()
+    }
+    fn item_indices(&self) -> I::Iter {
+        self.fragment.item_indices()
+    }
+    fn split_input(&self, index: usize) -> (Self, Self) {
+        let (head, tail) = self.fragment.split_input(index);
+        (
+            Located {
+                fragment: head,
+                offset: self.offset,
+            },
+            Located {
+                fragment: tail,
+                offset: self.offset + index,
+            },
+        )
+    }
+    fn input_bytes(&self) -> &[u8] {
+        self.fragment.input_bytes()
+    }
+}
+
+impl<I, T: Tag<I>> Tag<Located<I>> for T {
+    fn match_start(&self, input: &Located<I>) -> Option<usize> {
+        self.match_start(&input.fragment)
+    }
+    fn is_prefixed_by(&self, input: &Located<I>) -> bool {
+        self.is_prefixed_by(&input.fragment)
+    }
+    fn expected(&self) -> Expected {
+        Tag::<I>::expected(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_split_input() {
+        assert_eq!("aåb".split_input(3), ("aå", "b"));
+        assert_eq!((&b"ab"[..]).split_input(1), (&b"a"[..], &b"b"[..]));
+        assert_eq!("ab".end(), "");
+    }
+    #[test]
+    fn test_tag() {
+        assert_eq!("HTTP/".match_start(&&b"HTTP/1.1"[..]), Some(5));
+        assert!("HTTP/".is_prefixed_by(&&b"HT"[..]));
+        assert!(!"HTTP/".is_prefixed_by(&"HTTP/1.1"));
+    }
+    #[test]
+    fn test_located() {
+        let (head, tail) = Located::new("aåb").split_input(3);
+        assert_eq!((head.fragment(), head.offset()), (&"aå", 0));
+        assert_eq!((tail.fragment(), tail.offset()), (&"b", 3));
+        assert_eq!(tail.split_input(1).1.offset(), 4);
+        assert_eq!("b".match_start(&tail), Some(1));
+    }
+}
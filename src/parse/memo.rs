@@ -0,0 +1,185 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::input::Input;
+use super::Parse;
+
+/// Results of memoized rules in one input, keyed by rule id and offset from the start of it
+struct Table {
+    /// Address of the first byte of the input, and of the byte after the last
+    start: usize,
+    end: usize,
+    results: HashMap<(&'static str, usize), Box<dyn Any>>,
+}
+
+impl Table {
+    /// Offset of the position in the input, if it is within it
+    /// Positions share the end of their input, and the input is borrowed while the table is
+    /// installed, so no other input can be at these addresses.
+    fn offset(&self, i: &[u8]) -> Option<usize> {
+        let at = i.as_ptr() as usize;
+        (at >= self.start && at + i.len() == self.end).then(|| at - self.start)
+    }
+}
+
+thread_local! {
+    static TABLE: RefCell<Option<Table>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with an empty memo table for `input`, so `memo` parsers within it parse at most once
+/// per position of the input
+/// Positions in other inputs, like text that is built while parsing, are parsed as is. The table is
+/// dropped afterwards, even if `f` panics, and the table of an enclosing `with_memo` is restored.
+pub fn with_memo<I: Input, T>(input: I, f: impl FnOnce() -> T) -> T {
+    let bytes = input.input_bytes();
+    let table = Table {
+        start: bytes.as_ptr() as usize,
+        end: bytes.as_ptr() as usize + bytes.len(),
+        results: HashMap::new(),
+    };
+    let _outer = Outer(TABLE.with(|t| t.replace(Some(table))));
+    f()
+}
+
+/// Restores the table of an enclosing `with_memo` when dropped
+struct Outer(Option<Table>);
+
+impl Drop for Outer {
+    fn drop(&mut self) {
+        TABLE.with(|t| t.replace(self.0.take()));
+    }
+}
+
+/// Memoizes the parser as the rule `rule`, when called within `with_memo`
+/// The first parse at a position is stored, and later parses at that position return a clone of it.
+/// Outside `with_memo` the parser is run as is.
+///
+/// Rules are identified by name only, so two different parsers must not share the id.
+/// Memoizing every rule that can be backtracked into makes a grammar parse in linear time.
+pub fn memo<I, O, P>(rule: &'static str, p: P) -> impl Fn(I) -> Parse<I, O>
+where
+    P: Fn(I) -> Parse<I, O>,
+    I: Input,
+    O: Clone + 'static,
+{
+    move |i: I| {
+        let stored = TABLE.with(|t| {
+            let table = t.borrow();
+            let table = table.as_ref()?;
+            let key = (rule, table.offset(i.input_bytes())?);
+            let result = table.results.get(&key);
+            Some((
+                key,
+                result
+                    .and_then(|r| r.downcast_ref::<Parse<usize, O>>())
+                    .cloned(),
+            ))
+        });
+        match stored {
+            // No table installed, or the position is in another input
+            None => p(i),
+            Some((_, Some(result))) => rebuild(i, result),
+            Some((key, None)) => {
+                let result = p(i);
+                let stored = match &result {
+                    Parse::Success(res, sur) => Parse::Success(res.clone(), sur.input_len()),
+                    Parse::Retreat(r) => Parse::Retreat(r.clone()),
+                    Parse::Halt(h) => Parse::Halt(h.clone()),
+                    Parse::Limit(res, sur) => Parse::Limit(res.clone(), sur.input_len()),
+                };
+                TABLE.with(|t| {
+                    if let Some(table) = t.borrow_mut().as_mut() {
+                        table.results.insert(key, Box::new(stored));
+                    }
+                });
+                result
+            }
+        }
+    }
+}
+
+/// Turns a stored result back into a parse of `i`
+fn rebuild<I: Input, O>(i: I, stored: Parse<usize, O>) -> Parse<I, O> {
+    let rest = |len: usize| i.split_input(i.input_len() - len).1;
+    match stored {
+        Parse::Success(res, len) => Parse::Success(res, rest(len)),
+        Parse::Retreat(r) => Parse::Retreat(r),
+        Parse::Halt(h) => Parse::Halt(h),
+        Parse::Limit(res, len) => Parse::Limit(res, rest(len)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::comb::{either, map};
+    use super::super::sequence::serial;
+    use super::super::str::{char, take_while};
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_memo() {
+        let calls = Cell::new(0);
+        let word = memo("word", |i: &str| {
+            calls.set(calls.get() + 1);
+            map(take_while(|c: char| c.is_alphabetic()), |w: &str| {
+                w.to_string()
+            })(i)
+        });
+        let statement = either(serial(&word, char(';')), serial(&word, char(',')));
+
+        assert_eq!(
+            statement("abc,"),
+            Parse::Success(("abc".to_string(), ','), "")
+        );
+        assert_eq!(calls.get(), 2);
+
+        calls.set(0);
+        let input = "abc,";
+        let result = with_memo(input, || statement(input));
+        assert_eq!(result, Parse::Success(("abc".to_string(), ','), ""));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_memo_positions() {
+        let calls = Cell::new(0);
+        let letter = memo("letter", |i: &str| {
+            calls.set(calls.get() + 1);
+            char('a')(i)
+        });
+        let pair = || serial(&letter, &letter);
+        let exclaimed = map(serial(pair(), char('!')), |(p, _)| p);
+        let input = "aa?";
+        let result = with_memo(input, || either(exclaimed, pair())(input));
+        assert_eq!(result, Parse::Success(('a', 'a'), "?"));
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_memo_inputs() {
+        let texts = ["xb".to_string(), "ab".to_string()];
+        let calls = Cell::new(0);
+        let letter = memo("letter", |i: &str| {
+            calls.set(calls.get() + 1);
+            char('a')(i)
+        });
+        // Only positions in the input of the table are memoized, so text made while parsing,
+        // which may be at the address of earlier text, is parsed as is
+        let input = "ab";
+        let (a, b) = with_memo(input, || {
+            let a = (letter(input), letter(input));
+            let b: Vec<_> = texts.iter().map(|t| letter(t).is_retreat()).collect();
+            (a, b)
+        });
+        assert_eq!(a, (Parse::Success('a', "b"), Parse::Success('a', "b")));
+        assert_eq!(b, vec![true, false]);
+        assert_eq!(calls.get(), 3);
+
+        // The table is restored after a panic
+        let panicked = std::panic::catch_unwind(|| with_memo("", || panic!()));
+        assert!(panicked.is_err());
+        assert!(TABLE.with(|t| t.borrow().is_none()));
+    }
+}
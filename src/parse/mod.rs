@@ -1,14 +1,35 @@
+pub mod abnf;
+pub mod binary;
+pub mod class;
 pub mod comb;
+pub mod diagnostic;
+pub mod error;
+pub mod escape;
+pub mod generate;
+pub mod input;
+pub mod memo;
+pub mod parser;
+pub mod permutation;
+pub mod pratt;
+pub mod recover;
 pub mod repeat;
+pub mod scan;
 pub mod sequence;
+pub mod state;
 pub mod str;
+pub mod stream;
+pub mod syntax;
 pub mod tools;
+pub mod trace;
 
-#[derive(Debug, PartialEq, Eq)]
+use self::error::{Error, Expected};
+use self::input::Input;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Parse<I, O> {
     Success(O, I),
-    Retreat(String),
-    Halt(String),
+    Retreat(Error),
+    Halt(Error),
     Limit(Option<O>, I),
 }
 
@@ -36,40 +57,16 @@ impl<I, O> Parse<I, O> {
     }
 }
 
-// mod generic {
-//     use std::{iter::Iterator, str::CharIndices};
-//     pub trait IsIterable {
-//         type Iterator;
-//         fn into_iterable(self) -> Self::Iterator;
-//         fn
-//     }
-//     impl IsIterable for &str {
-//         type Iterator = CharIndices;
-//         fn into_iterable(self) -> CharIndices {
-//             self.char_indices()
-//         }
-//     }
-// }
-
-//     pub fn pop<I>(input: I) -> Parse<I, char>
-//     where
-//         I: IsIterable + Index<I>,
-//     {
-//         let mut iter = input.char_indices();
-//         match iter.next() {
-//             Some((_, c)) => {
-//                 if let Some((next, _)) = iter.next() {
-//                     Parse::Success(c, &input[next..])
-//                 } else {
-//                     Parse::Success(c, input.get_empty())
-//                 }
-//             }
-//             None => Parse::Deficient(None),
-//         }
-//     }
-
-// pub fn satisfy<I, O, P, F>(p: P, f: F) -> impl Fn(I) -> Parse<I, O>
-// where P: Fn(I) ->
-// {
-//     |i: I| match p(i) {}
-// }
+impl<I: Input, O> Parse<I, O> {
+    /// Treats the input as complete, so a limit is either a success or an error
+    /// The error offset is relative to the input given to the parser, see `Error::offset`
+    /// Input that ends too early is an error at its end.
+    pub fn finish(self) -> Result<(O, I), Error> {
+        match self {
+            Parse::Success(res, sur) => Ok((res, sur)),
+            Parse::Limit(Some(res), sur) => Ok((res, sur)),
+            Parse::Limit(None, sur) => Err(Error::new(&sur.end(), Expected::More)),
+            Parse::Retreat(e) | Parse::Halt(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use super::input::Input;
+use super::{comb, repeat, sequence, tools, Parse};
+
+/// Method-chaining versions of the combinators, implemented for every parser
+///
+/// Every method is the free function of the same name, with `self` as the first parser:
+/// `char('a').or(char('b')).repeat()` is `repeat_any(either(char('a'), char('b')))`.
+pub trait Parser<I, O>: Fn(I) -> Parse<I, O> {
+    /// See `comb::map`
+    fn map<M, F>(self, func: F) -> impl Fn(I) -> Parse<I, M>
+    where
+        Self: Sized,
+        F: Fn(O) -> M,
+        I: Copy,
+    {
+        comb::map(self, func)
+    }
+
+    /// See `comb::map_result`
+    fn map_result<M, E, F>(self, func: F, label: &'static str) -> impl Fn(I) -> Parse<I, M>
+    where
+        Self: Sized,
+        F: Fn(O) -> Result<M, E>,
+        I: Input,
+    {
+        comb::map_result(self, func, label)
+    }
+
+    /// Applies `next` after this parser, see `sequence::serial`
+    fn then<O2, P>(self, next: P) -> impl Fn(I) -> Parse<I, (O, O2)>
+    where
+        Self: Sized,
+        P: Fn(I) -> Parse<I, O2>,
+        I: Copy,
+    {
+        sequence::serial(self, next)
+    }
+
+    /// Tries `other` if this parser retreats, see `comb::either`
+    fn or<P>(self, other: P) -> impl Fn(I) -> Parse<I, O>
+    where
+        Self: Sized,
+        P: Fn(I) -> Parse<I, O>,
+        I: Copy,
+    {
+        comb::either(self, other)
+    }
+
+    /// See `comb::optional`
+    fn optional(self) -> impl Fn(I) -> Parse<I, Option<O>>
+    where
+        Self: Sized,
+        I: Copy,
+    {
+        comb::optional(self)
+    }
+
+    /// Zero or more repetitions, see `repeat::repeat_any`
+    fn repeat(self) -> impl Fn(I) -> Parse<I, Vec<O>>
+    where
+        Self: Sized,
+        I: Copy,
+    {
+        repeat::repeat_any(self)
+    }
+
+    /// One or more repetitions, see `repeat::repeat_some`
+    fn repeat_some(self) -> impl Fn(I) -> Parse<I, Vec<O>>
+    where
+        Self: Sized,
+        I: Copy,
+    {
+        repeat::repeat_some(self)
+    }
+
+    /// See `tools::label`
+    fn label(self, label: &'static str) -> impl Fn(I) -> Parse<I, O>
+    where
+        Self: Sized,
+        I: Input,
+    {
+        tools::label(self, label)
+    }
+
+    /// See `tools::halt`
+    fn halt(self) -> impl Fn(I) -> Parse<I, O>
+    where
+        Self: Sized,
+    {
+        tools::halt(self)
+    }
+
+    /// Applies `before` first and discards its output, see `sequence::preceded`
+    fn preceded_by<O2, P>(self, before: P) -> impl Fn(I) -> Parse<I, O>
+    where
+        Self: Sized,
+        P: Fn(I) -> Parse<I, O2>,
+        I: Copy,
+    {
+        sequence::preceded(before, self)
+    }
+
+    /// Applies `after` last and discards its output, see `sequence::terminated`
+    fn terminated_by<O2, P>(self, after: P) -> impl Fn(I) -> Parse<I, O>
+    where
+        Self: Sized,
+        P: Fn(I) -> Parse<I, O2>,
+        I: Copy,
+    {
+        sequence::terminated(self, after)
+    }
+}
+
+impl<I, O, P> Parser<I, O> for P where P: Fn(I) -> Parse<I, O> {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::str::{char, digit, literal};
+    use super::*;
+
+    #[test]
+    fn test_chaining() {
+        let sign = char('-').or(char('+')).optional();
+        assert_eq!(sign("-1"), Parse::Success(Some('-'), "1"));
+        assert_eq!(sign("1"), Parse::Success(None, "1"));
+
+        let digits = digit
+            .repeat_some()
+            .map(|d| d.iter().fold(0u32, |a, &d| a * 10 + d as u32));
+        let number = digits.preceded_by(char('#')).terminated_by(char(';'));
+        assert_eq!(number("#12;x"), Parse::Success(12, "x"));
+        assert!(number("12;").is_retreat());
+
+        let pair = literal("a")
+            .then(char('=').halt())
+            .map(|(a, _)| a)
+            .label("pair");
+        assert_eq!(pair("a="), Parse::Success("a", ""));
+        match pair("ab") {
+            Parse::Halt(e) => assert_eq!(e.context(), &["pair"]),
+            p => panic!("{p:?}"),
+        }
+    }
+}
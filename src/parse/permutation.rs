@@ -0,0 +1,186 @@
+use super::comb::map;
+use super::error::Error;
+use super::Parse;
+
+// Parsers of items that may come in any order, like cookie attributes or media type parameters
+//
+// Each step tries the parsers that haven't matched yet in order, and takes the first that succeeds.
+// A limit ends the parse, since more input could make another parser match instead. Separators
+// between the items are part of the item parsers, like `preceded(literal("; "), value)`.
+
+/// Parsers that can be applied in any order, implemented for tuples of up to 12 parsers
+pub trait Permutation<I, O> {
+    /// The outputs as options, for the parsers that may not match
+    type Options;
+
+    /// Applies each parser at most once in any order, until none of the rest match
+    /// If `all`, the parsers must all match, so it retreats or limits without a result otherwise.
+    fn parse_permutation(&self, i: I, all: bool) -> Parse<I, Self::Options>;
+
+    /// The outputs of a permutation where all parsers matched
+    fn unwrap(options: Self::Options) -> O;
+}
+
+macro_rules! impl_permutation {
+    ($($p:ident $o:ident),+) => {
+        impl<I, $($p, $o,)+> Permutation<I, ($($o,)+)> for ($($p,)+)
+        where
+            I: Copy,
+            $($p: Fn(I) -> Parse<I, $o>,)+
+        {
+            type Options = ($(Option<$o>,)+);
+
+            #[allow(non_snake_case)]
+            fn parse_permutation(&self, i: I, all: bool) -> Parse<I, Self::Options> {
+                let ($($p,)+) = self;
+                $(let mut $o: Option<$o> = None;)+
+                let mut rest = i;
+                loop {
+                    let mut matched = false;
+                    let mut limited = false;
+                    let mut error: Option<Error> = None;
+                    $(
+                        if !matched && $o.is_none() {
+                            match $p(rest) {
+                                Parse::Success(res, sur) => {
+                                    $o = Some(res);
+                                    rest = sur;
+                                    matched = true;
+                                }
+                                Parse::Limit(Some(res), sur) => {
+                                    $o = Some(res);
+                                    rest = sur;
+                                    matched = true;
+                                    limited = true;
+                                }
+                                Parse::Limit(None, _) => limited = true,
+                                Parse::Retreat(e) => {
+                                    error = Some(match error {
+                                        Some(error) => error.merge(e),
+                                        None => e,
+                                    })
+                                }
+                                Parse::Halt(h) => return Parse::Halt(h),
+                            }
+                        }
+                    )+
+                    let complete = $($o.is_some())&&+;
+                    if limited {
+                        return match all && !complete {
+                            true => Parse::Limit(None, i),
+                            false => Parse::Limit(Some(($($o,)+)), rest),
+                        };
+                    }
+                    if complete || !matched {
+                        return match (all && !complete, error) {
+                            (true, Some(e)) => Parse::Retreat(e),
+                            _ => Parse::Success(($($o,)+), rest),
+                        };
+                    }
+                }
+            }
+
+            #[allow(non_snake_case)]
+            fn unwrap(($($o,)+): Self::Options) -> ($($o,)+) {
+                ($($o.expect("all parsers of a permutation matched"),)+)
+            }
+        }
+    };
+}
+
+impl_permutation!(P1 O1, P2 O2);
+impl_permutation!(P1 O1, P2 O2, P3 O3);
+impl_permutation!(P1 O1, P2 O2, P3 O3, P4 O4);
+impl_permutation!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5);
+impl_permutation!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6);
+impl_permutation!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7);
+impl_permutation!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7, P8 O8);
+impl_permutation!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7, P8 O8, P9 O9);
+impl_permutation!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7, P8 O8, P9 O9, P10 O10);
+impl_permutation!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7, P8 O8, P9 O9, P10 O10, P11 O11);
+impl_permutation!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7, P8 O8, P9 O9, P10 O10, P11 O11, P12 O12);
+
+/// Applies a tuple of parsers in any order, of which each must match exactly once
+/// The outputs are in the order of the parsers, not of the input.
+pub fn permutation<I, O, T>(parsers: T) -> impl Fn(I) -> Parse<I, O>
+where
+    T: Permutation<I, O>,
+    I: Copy,
+{
+    move |i: I| map(|i| parsers.parse_permutation(i, true), T::unwrap)(i)
+}
+
+/// Applies a tuple of parsers in any order, of which each may match once
+/// Gives `None` for the parsers that didn't match, so it never retreats.
+pub fn interleave<I, O, T>(parsers: T) -> impl Fn(I) -> Parse<I, T::Options>
+where
+    T: Permutation<I, O>,
+    I: Copy,
+{
+    move |i: I| parsers.parse_permutation(i, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sequence::preceded;
+    use super::super::str::{char, literal, take_some_while};
+    use super::*;
+    use crate::serial;
+
+    /// A media type parameter, like `; charset=utf-8`
+    fn param<'a>(name: &'static str) -> impl Fn(&'a str) -> Parse<&'a str, &'a str> {
+        preceded(
+            serial!(literal("; "), literal(name), char('=')),
+            take_some_while(|c: char| c.is_ascii_alphanumeric() || c == '-'),
+        )
+    }
+
+    #[test]
+    fn test_permutation() {
+        let params = permutation((param("charset"), param("boundary")));
+        assert_eq!(
+            params("; boundary=x; charset=utf-8\r\n"),
+            Parse::Success(("utf-8", "x"), "\r\n")
+        );
+        assert_eq!(
+            params("; charset=utf-8; boundary=x\r\n"),
+            Parse::Success(("utf-8", "x"), "\r\n")
+        );
+        assert!(params("; charset=utf-8\r\n").is_retreat());
+        assert!(params("; charset=utf-8; charset=x\r\n").is_retreat());
+        assert_eq!(
+            params("; charset=utf-8; bound"),
+            Parse::Limit(None, "; charset=utf-8; bound")
+        );
+        assert_eq!(
+            params("; charset=utf-8; boundary=x"),
+            Parse::Limit(Some(("utf-8", "x")), "")
+        );
+
+        let letters = permutation((char('a'), char('b'), char('c')));
+        assert_eq!(letters("cabd"), Parse::Success(('a', 'b', 'c'), "d"));
+        match letters("ba d") {
+            Parse::Retreat(e) => assert_eq!(e.offset(&"ba d"), 2),
+            p => panic!("{p:?}"),
+        }
+    }
+
+    #[test]
+    fn test_interleave() {
+        let params = interleave((param("charset"), param("boundary")));
+        assert_eq!(
+            params("; charset=utf-8\r\n"),
+            Parse::Success((Some("utf-8"), None), "\r\n")
+        );
+        assert_eq!(
+            params("; boundary=x; boundary=y\r\n"),
+            Parse::Success((None, Some("x")), "; boundary=y\r\n")
+        );
+        assert_eq!(params("\r\n"), Parse::Success((None, None), "\r\n"));
+        assert_eq!(params(""), Parse::Limit(Some((None, None)), ""));
+        assert_eq!(
+            params("; charset=utf-8; bo"),
+            Parse::Limit(Some((Some("utf-8"), None)), "; bo")
+        );
+    }
+}
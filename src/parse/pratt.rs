@@ -0,0 +1,373 @@
+use super::error::{Error, Expected};
+use super::input::Input;
+use super::Parse;
+
+/// Default limit on how deeply operators nest
+const MAX_DEPTH: usize = 128;
+
+/// Associativity of an infix operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`
+    Right,
+}
+
+type OpParser<'a, I> = Box<dyn Fn(I) -> Parse<I, ()> + 'a>;
+
+struct Unary<'a, I, O> {
+    op: OpParser<'a, I>,
+    power: u8,
+    build: Box<dyn Fn(O) -> O + 'a>,
+}
+
+struct Binary<'a, I, O> {
+    op: OpParser<'a, I>,
+    power: u8,
+    assoc: Assoc,
+    build: Box<dyn Fn(O, O) -> O + 'a>,
+}
+
+/// Operator-precedence parser builder
+///
+/// Parses expressions of atoms joined by prefix, infix and postfix operators.
+/// Each operator has a power, where higher powers bind tighter, and a callback building the output.
+/// Whitespace between operators and atoms is up to the operator and atom parsers.
+///
+/// ```ignore
+/// let expression = Pratt::new(number)
+///     .infix(char('+'), 1, Assoc::Left, |a, b| a + b)
+///     .infix(char('*'), 2, Assoc::Left, |a, b| a * b)
+///     .prefix(char('-'), 3, |a| -a);
+/// expression.parse("-1+2*3")
+/// ```
+///
+/// For parenthesized sub-expressions, let the atom parser call a function building the expression parser.
+pub struct Pratt<'a, I, O> {
+    atom: Box<dyn Fn(I) -> Parse<I, O> + 'a>,
+    prefix: Vec<Unary<'a, I, O>>,
+    infix: Vec<Binary<'a, I, O>>,
+    postfix: Vec<Unary<'a, I, O>>,
+    max_depth: usize,
+}
+
+impl<'a, I, O: 'a> Pratt<'a, I, O>
+where
+    I: Input + 'a,
+{
+    pub fn new<P>(atom: P) -> Self
+    where
+        P: Fn(I) -> Parse<I, O> + 'a,
+    {
+        Pratt {
+            atom: Box::new(atom),
+            prefix: Vec::new(),
+            infix: Vec::new(),
+            postfix: Vec::new(),
+            max_depth: MAX_DEPTH,
+        }
+    }
+
+    /// Halts on operands nested deeper than this, like `- - - a` or `a ^ b ^ c`, so input can't
+    /// overflow the stack
+    /// Sub-expressions parsed by the atom parser, like parenthesized ones, aren't counted here.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    pub fn prefix<P, T, F>(mut self, op: P, power: u8, build: F) -> Self
+    where
+        P: Fn(I) -> Parse<I, T> + 'a,
+        F: Fn(O) -> O + 'a,
+    {
+        self.prefix.push(Unary {
+            op: discard(op),
+            power,
+            build: Box::new(build),
+        });
+        self
+    }
+
+    pub fn infix<P, T, F>(mut self, op: P, power: u8, assoc: Assoc, build: F) -> Self
+    where
+        P: Fn(I) -> Parse<I, T> + 'a,
+        F: Fn(O, O) -> O + 'a,
+    {
+        self.infix.push(Binary {
+            op: discard(op),
+            power,
+            assoc,
+            build: Box::new(build),
+        });
+        self
+    }
+
+    pub fn postfix<P, T, F>(mut self, op: P, power: u8, build: F) -> Self
+    where
+        P: Fn(I) -> Parse<I, T> + 'a,
+        F: Fn(O) -> O + 'a,
+    {
+        self.postfix.push(Unary {
+            op: discard(op),
+            power,
+            build: Box::new(build),
+        });
+        self
+    }
+
+    pub fn parse(&self, i: I) -> Parse<I, O> {
+        match self.expression(i, 0, 0) {
+            Parse::Limit(None, _) => Parse::Limit(None, i),
+            p => p,
+        }
+    }
+
+    /// Returns a parser of the expressions
+    pub fn parser(self) -> impl Fn(I) -> Parse<I, O> + 'a {
+        move |i: I| self.parse(i)
+    }
+
+    /// Parses an expression of operators binding at least as tight as `min_power`
+    /// Powers here are doubled, so left and right binding power of an infix operator can differ
+    fn expression(&self, i: I, min_power: u16, depth: usize) -> Parse<I, O> {
+        if depth == self.max_depth {
+            return Parse::Halt(Error::new(&i, Expected::Label("shallower expression")));
+        }
+        let (mut lhs, mut rest) = match self.operand(i, depth) {
+            Parse::Success(res, sur) => (res, sur),
+            p => return p,
+        };
+        'operators: loop {
+            for postfix in self.postfix.iter() {
+                let (sur, limited) = match operator(&postfix.op, rest) {
+                    Some(Operator::Matched(sur, limited)) => (sur, limited),
+                    Some(Operator::Undecided) => return Parse::Limit(Some(lhs), rest),
+                    Some(Operator::Halt(h)) => return Parse::Halt(h),
+                    None => continue,
+                };
+                // An operator that consumes nothing would apply forever
+                if 2 * (postfix.power as u16) < min_power || sur.input_len() == rest.input_len() {
+                    continue;
+                }
+                lhs = (postfix.build)(lhs);
+                if limited {
+                    return Parse::Limit(Some(lhs), sur);
+                }
+                rest = sur;
+                continue 'operators;
+            }
+            for infix in self.infix.iter() {
+                let (sur, limited) = match operator(&infix.op, rest) {
+                    Some(Operator::Matched(sur, limited)) => (sur, limited),
+                    Some(Operator::Undecided) => return Parse::Limit(Some(lhs), rest),
+                    Some(Operator::Halt(h)) => return Parse::Halt(h),
+                    None => continue,
+                };
+                let power = 2 * infix.power as u16;
+                let (left, right) = match infix.assoc {
+                    Assoc::Left => (power, power + 1),
+                    Assoc::Right => (power + 1, power),
+                };
+                if left < min_power {
+                    continue;
+                }
+                match self.expression(sur, right, depth + 1) {
+                    Parse::Success(rhs, sur) if !limited => {
+                        lhs = (infix.build)(lhs, rhs);
+                        rest = sur;
+                        continue 'operators;
+                    }
+                    Parse::Success(rhs, sur) | Parse::Limit(Some(rhs), sur) => {
+                        return Parse::Limit(Some((infix.build)(lhs, rhs)), sur)
+                    }
+                    p => return p,
+                }
+            }
+            return Parse::Success(lhs, rest);
+        }
+    }
+
+    /// Parses an atom, or a prefix operator and its operand
+    fn operand(&self, i: I, depth: usize) -> Parse<I, O> {
+        for prefix in self.prefix.iter() {
+            let (sur, limited) = match operator(&prefix.op, i) {
+                Some(Operator::Matched(sur, limited)) => (sur, limited),
+                Some(Operator::Undecided) => return Parse::Limit(None, i),
+                Some(Operator::Halt(h)) => return Parse::Halt(h),
+                None => continue,
+            };
+            return match self.expression(sur, 2 * prefix.power as u16, depth + 1) {
+                Parse::Success(res, sur) if !limited => Parse::Success((prefix.build)(res), sur),
+                Parse::Success(res, sur) | Parse::Limit(Some(res), sur) => {
+                    Parse::Limit(Some((prefix.build)(res)), sur)
+                }
+                p => p,
+            };
+        }
+        (self.atom)(i)
+    }
+}
+
+/// Outcome of trying an operator
+enum Operator<I> {
+    /// The operator matched, and whether the match relied on the input ending
+    Matched(I, bool),
+    /// The input ended before telling whether the operator is there
+    Undecided,
+    Halt(Error),
+}
+
+/// Tries an operator, returning `None` when it retreats
+fn operator<I>(op: &OpParser<'_, I>, i: I) -> Option<Operator<I>> {
+    match op(i) {
+        Parse::Success(_, sur) => Some(Operator::Matched(sur, false)),
+        Parse::Limit(Some(_), sur) => Some(Operator::Matched(sur, true)),
+        Parse::Limit(None, _) => Some(Operator::Undecided),
+        Parse::Halt(h) => Some(Operator::Halt(h)),
+        Parse::Retreat(_) => None,
+    }
+}
+
+fn discard<'a, I, T, P>(op: P) -> OpParser<'a, I>
+where
+    P: Fn(I) -> Parse<I, T> + 'a,
+    I: 'a,
+{
+    Box::new(move |i: I| match op(i) {
+        Parse::Success(_, sur) => Parse::Success((), sur),
+        Parse::Retreat(r) => Parse::Retreat(r),
+        Parse::Halt(h) => Parse::Halt(h),
+        Parse::Limit(res, sur) => Parse::Limit(res.map(|_| ()), sur),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::comb::map;
+    use super::super::sequence::between;
+    use super::super::str::{char, digit, literal, take_some_while, take_while};
+    use super::*;
+    use crate::either;
+
+    fn spaced<'a, O>(
+        p: impl Fn(&'a str) -> Parse<&'a str, O>,
+    ) -> impl Fn(&'a str) -> Parse<&'a str, O> {
+        between(take_while(|c| c == ' '), p, take_while(|c| c == ' '))
+    }
+
+    fn atom(i: &str) -> Parse<&str, String> {
+        spaced(either!(
+            map(
+                take_some_while(|c: char| c.is_ascii_alphanumeric()),
+                |s: &str| { s.to_string() }
+            ),
+            between(char('('), expression, char(')')),
+        ))(i)
+    }
+
+    fn expression(i: &str) -> Parse<&str, String> {
+        Pratt::new(atom)
+            .infix(spaced(char('+')), 1, Assoc::Left, |a, b| {
+                format!("({a} + {b})")
+            })
+            .infix(spaced(char('-')), 1, Assoc::Left, |a, b| {
+                format!("({a} - {b})")
+            })
+            .infix(spaced(char('*')), 2, Assoc::Left, |a, b| {
+                format!("({a} * {b})")
+            })
+            .infix(spaced(char('^')), 4, Assoc::Right, |a, b| {
+                format!("({a} ^ {b})")
+            })
+            .prefix(spaced(char('-')), 3, |a| format!("(-{a})"))
+            .postfix(spaced(char('!')), 5, |a| format!("({a}!)"))
+            .parse(i)
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(
+            expression("1 + 2 * 3;"),
+            Parse::Success("(1 + (2 * 3))".to_string(), ";")
+        );
+        assert_eq!(
+            expression("1 - 2 - 3;"),
+            Parse::Success("((1 - 2) - 3)".to_string(), ";")
+        );
+        assert_eq!(
+            expression("2 ^ 3 ^ 4;"),
+            Parse::Success("(2 ^ (3 ^ 4))".to_string(), ";")
+        );
+        assert_eq!(
+            expression("-a ^ b! * (c + d);"),
+            Parse::Success("((-(a ^ (b!))) * (c + d))".to_string(), ";")
+        );
+        assert!(expression("* 2").is_retreat());
+        assert!(expression("1 + ;").is_retreat());
+    }
+
+    #[test]
+    fn test_limit() {
+        // The trailing whitespace of the last atom may continue
+        assert_eq!(expression("1 + 2"), Parse::Limit(None, "1 + 2"));
+        assert_eq!(expression("1 +"), Parse::Limit(None, "1 +"));
+
+        let sum = Pratt::new(digit)
+            .infix(char('+'), 1, Assoc::Left, |a, b| a + b)
+            .parser();
+        assert_eq!(sum("1+2"), Parse::Limit(Some(3), ""));
+        assert_eq!(sum("1+2+"), Parse::Limit(None, "1+2+"));
+    }
+
+    #[test]
+    fn test_filter() {
+        #[derive(Debug, PartialEq)]
+        enum Filter {
+            Field(String),
+            Not(Box<Filter>),
+            And(Box<Filter>, Box<Filter>),
+            Or(Box<Filter>, Box<Filter>),
+        }
+        let filter = Pratt::new(map(
+            take_some_while(|c: char| c.is_alphabetic()),
+            |f: &str| Filter::Field(f.to_string()),
+        ))
+        .infix(literal("|"), 1, Assoc::Left, |a, b| {
+            Filter::Or(Box::new(a), Box::new(b))
+        })
+        .infix(literal("&"), 2, Assoc::Left, |a, b| {
+            Filter::And(Box::new(a), Box::new(b))
+        })
+        .prefix(literal("!"), 3, |a| Filter::Not(Box::new(a)))
+        .parser();
+        let field = |f: &str| Box::new(Filter::Field(f.to_string()));
+        assert_eq!(
+            filter("a|!b&c "),
+            Parse::Success(
+                Filter::Or(
+                    field("a"),
+                    Box::new(Filter::And(Box::new(Filter::Not(field("b"))), field("c")))
+                ),
+                " "
+            )
+        );
+    }
+
+    #[test]
+    fn test_progress() {
+        let negations = || Pratt::new(digit).prefix(char('-'), 1, |a| a);
+        assert_eq!(
+            negations().max_depth(3).parse("--1;"),
+            Parse::Success(1, ";")
+        );
+        assert!(negations().max_depth(3).parse("---1;").is_halt());
+        let deep = "-".repeat(100_000) + "1;";
+        assert!(negations().parse(&deep).is_halt());
+
+        // Postfix operators that match nothing aren't applied
+        let bangs = Pratt::new(digit).postfix(take_while(|c| c == '!'), 1, |a| a + 1);
+        assert_eq!(bangs.parse("1!!;"), Parse::Success(2, ";"));
+    }
+}
@@ -0,0 +1,139 @@
+use std::cell::RefCell;
+
+use super::error::Error;
+use super::input::Input;
+use super::Parse;
+
+thread_local! {
+    static ERRORS: RefCell<Option<Vec<Error>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with error recovery enabled, returning its result and the errors recovered from
+pub fn with_recovery<T>(f: impl FnOnce() -> T) -> (T, Vec<Error>) {
+    let outer = ERRORS.with(|e| e.replace(Some(Vec::new())));
+    let result = f();
+    let errors = ERRORS.with(|e| e.replace(outer)).unwrap_or_default();
+    (result, errors)
+}
+
+/// Recovers from the parser failing, when called within `with_recovery`
+///
+/// On a retreat or halt the error is recorded, the input is skipped from where the error occurred
+/// up to where `sync` matches, and `placeholder` is returned in place of the output. `sync` itself
+/// is not consumed. If the input ends before `sync` is decided, the placeholder is returned as a
+/// limit. Errors recovered from within the parser are dropped when it fails, since its input is
+/// skipped.
+///
+/// Recovering must consume input, so a repetition of it can't go on forever. If `sync` matches
+/// where the parser started, the error is passed on instead.
+/// Outside `with_recovery` the parser is run as is.
+pub fn recover_with<I, O, O2, P, S>(p: P, sync: S, placeholder: O) -> impl Fn(I) -> Parse<I, O>
+where
+    P: Fn(I) -> Parse<I, O>,
+    S: Fn(I) -> Parse<I, O2>,
+    I: Input,
+    O: Clone,
+{
+    move |i: I| {
+        let logged = ERRORS.with(|errors| errors.borrow().as_ref().map(Vec::len));
+        let (error, halted) = match p(i) {
+            Parse::Retreat(e) => (e, false),
+            Parse::Halt(e) => (e, true),
+            p => return p,
+        };
+        let Some(logged) = logged else {
+            return pass_on(error, halted);
+        };
+        truncate(logged);
+        let from = i.split_input(error.offset(&i)).1;
+        for (index, _) in from.item_indices() {
+            let rest = from.split_input(index).1;
+            let limited = match sync(rest) {
+                Parse::Success(_, _) => false,
+                Parse::Limit(_, _) => true,
+                Parse::Retreat(_) | Parse::Halt(_) => continue,
+            };
+            if rest.input_len() == i.input_len() {
+                return pass_on(error, halted);
+            }
+            record(error);
+            return match limited {
+                true => Parse::Limit(Some(placeholder.clone()), rest),
+                false => Parse::Success(placeholder.clone(), rest),
+            };
+        }
+        record(error);
+        Parse::Limit(Some(placeholder.clone()), i.end())
+    }
+}
+
+fn pass_on<I, O>(error: Error, halted: bool) -> Parse<I, O> {
+    match halted {
+        true => Parse::Halt(error),
+        false => Parse::Retreat(error),
+    }
+}
+
+/// Drops the errors recorded after the first `len`
+fn truncate(len: usize) {
+    ERRORS.with(|errors| {
+        if let Some(errors) = errors.borrow_mut().as_mut() {
+            errors.truncate(len)
+        }
+    })
+}
+
+fn record(error: Error) {
+    ERRORS.with(|errors| {
+        if let Some(errors) = errors.borrow_mut().as_mut() {
+            errors.push(error)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::repeat::{repeat_any, separated_items};
+    use super::super::sequence::terminated;
+    use super::super::str::{char, char_of, digit};
+    use super::super::tools::halt;
+    use super::*;
+
+    #[test]
+    fn test_recover_with() {
+        let item = || recover_with(digit, char_of(",;"), 0);
+        let list = separated_items(char(','), item());
+
+        // Only recovers within `with_recovery`
+        assert!(item()("x").is_retreat());
+        assert!(recover_with(halt(digit), char(','), 0)("x").is_halt());
+        assert_eq!(list("1,x;"), Parse::Success(vec![1], ",x;"));
+
+        let (result, errors) = with_recovery(|| list("1,xy,3,x5;"));
+        assert_eq!(result, Parse::Success(vec![1, 0, 3, 0], ";"));
+        let offsets: Vec<_> = errors.iter().map(|e| e.offset(&"1,xy,3,x5;")).collect();
+        assert_eq!(offsets, vec![2, 7]);
+
+        // Recovering must consume input, so an empty item ends the list
+        let (result, errors) = with_recovery(|| list("1,,5;"));
+        assert_eq!(result, Parse::Success(vec![1], ",,5;"));
+        assert!(errors.is_empty());
+        let (result, _) = with_recovery(|| repeat_any(item())(";"));
+        assert_eq!(result, Parse::Success(vec![], ";"));
+
+        let (result, errors) = with_recovery(|| list("1,xy"));
+        assert_eq!(result, Parse::Limit(Some(vec![1, 0]), ""));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_rollback() {
+        // The error of the digit is dropped when the pair it was in is recovered from
+        let digit = recover_with(digit, char(';'), 0);
+        let pair = recover_with(terminated(digit, char('!')), char(';'), 9);
+        let (result, errors) = with_recovery(|| pair("x;!"));
+        assert_eq!(result, Parse::Success(9, ";!"));
+        let offsets: Vec<_> = errors.iter().map(|e| e.offset(&"x;!")).collect();
+        assert_eq!(offsets, vec![1]);
+    }
+}
@@ -1,4 +1,8 @@
-use super::{comb::map_option, Parse};
+use std::borrow::Cow;
+
+use super::error::{Error, Expected};
+use super::input::Input;
+use super::{comb, sequence, Parse};
 
 /// Applies a parser zero or more times and returns a vector with the results
 pub fn repeat_any<I, O, P>(p: P) -> impl Fn(I) -> Parse<I, Vec<O>>
@@ -33,17 +37,27 @@ where
 pub fn repeat_some<I, O, P>(p: P) -> impl Fn(I) -> Parse<I, Vec<O>>
 where
     P: Fn(I) -> Parse<I, O>,
-    O: std::fmt::Debug,
     I: Copy,
 {
-    map_option(repeat_any(p), |res| {
-        println!("res: {:?}", res);
-        if res.len() > 0 {
-            Some(res)
-        } else {
-            None
-        }
-    })
+    move |i: I| match p(i) {
+        Parse::Success(first, sur) => match repeat_any(&p)(sur) {
+            Parse::Success(mut rest, sur) => {
+                rest.insert(0, first);
+                Parse::Success(rest, sur)
+            }
+            Parse::Limit(rest, sur) => {
+                let mut rest = rest.unwrap_or_default();
+                rest.insert(0, first);
+                Parse::Limit(Some(rest), sur)
+            }
+            Parse::Retreat(r) => Parse::Retreat(r),
+            Parse::Halt(h) => Parse::Halt(h),
+        },
+        Parse::Retreat(r) => Parse::Retreat(r),
+        Parse::Halt(h) => Parse::Halt(h),
+        Parse::Limit(Some(r), s) => Parse::Limit(Some(vec![r]), s),
+        Parse::Limit(None, _) => Parse::Limit(None, i),
+    }
 }
 
 pub fn separated_items<I, O1, O2, P1, P2>(
@@ -67,7 +81,7 @@ where
                     rest_unassumed = sur;
                 }
                 Parse::Retreat(_) => return Parse::Success(list, rest_unassumed),
-                Parse::Halt(_) => return Parse::Halt(format!("Halted at parsing item")),
+                Parse::Halt(h) => return Parse::Halt(h),
                 Parse::Limit(res, sur) => {
                     if let Some(r) = res {
                         list.push(r);
@@ -80,13 +94,231 @@ where
                     rest = sur;
                 }
                 Parse::Retreat(_) => return Parse::Success(list, rest),
-                Parse::Halt(_) => return Parse::Halt(format!("Halted at parsing item")),
+                Parse::Halt(h) => return Parse::Halt(h),
                 Parse::Limit(_, _) => return Parse::Limit(Some(list), rest_unassumed),
             }
         }
     }
 }
 
+/// Applies a parser `min` to `max` times, folding the results into the accumulator from `init`
+///
+/// Retreats with the error of the failing repetition if there are fewer than `min`.
+/// Halts with `Expected::AtMost` if the parser would succeed more than `max` times,
+/// so input can't make the repetition grow without bounds.
+/// A success that consumes nothing ends the repetition without being folded, since it would
+/// otherwise repeat forever.
+pub fn fold_range<I, O, A, P, N, F>(
+    min: usize,
+    max: usize,
+    p: P,
+    init: N,
+    fold: F,
+) -> impl Fn(I) -> Parse<I, A>
+where
+    P: Fn(I) -> Parse<I, O>,
+    N: Fn() -> A,
+    F: Fn(A, O) -> A,
+    I: Input,
+{
+    move |i: I| {
+        let mut acc = init();
+        let mut count = 0;
+        let mut rest = i;
+        loop {
+            match p(rest) {
+                Parse::Success(_, sur) if sur.input_len() == rest.input_len() => {
+                    return match count < min {
+                        true => Parse::Retreat(Error::new(&rest, Expected::Other)),
+                        false => Parse::Success(acc, rest),
+                    };
+                }
+                Parse::Success(_, _) | Parse::Limit(Some(_), _) if count == max => {
+                    return Parse::Halt(Error::new(&rest, Expected::AtMost(max)))
+                }
+                Parse::Success(res, sur) => {
+                    acc = fold(acc, res);
+                    count += 1;
+                    rest = sur;
+                }
+                Parse::Retreat(r) if count < min => return Parse::Retreat(r),
+                Parse::Retreat(_) => return Parse::Success(acc, rest),
+                Parse::Halt(h) => return Parse::Halt(h),
+                Parse::Limit(res, sur) => {
+                    if let Some(res) = res {
+                        acc = fold(acc, res);
+                        count += 1;
+                        rest = sur;
+                    }
+                    return match count < min {
+                        true => Parse::Limit(None, i),
+                        false => Parse::Limit(Some(acc), rest),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Applies a parser zero or more times, folding the results into the accumulator from `init`
+pub fn fold_many<I, O, A, P, N, F>(p: P, init: N, fold: F) -> impl Fn(I) -> Parse<I, A>
+where
+    P: Fn(I) -> Parse<I, O>,
+    N: Fn() -> A,
+    F: Fn(A, O) -> A,
+    I: Input,
+{
+    fold_range(0, usize::MAX, p, init, fold)
+}
+
+/// Applies a parser `min` to `max` times and returns a vector with the results
+/// See `fold_range` for the errors
+pub fn repeat_range<I, O, P>(min: usize, max: usize, p: P) -> impl Fn(I) -> Parse<I, Vec<O>>
+where
+    P: Fn(I) -> Parse<I, O>,
+    I: Input,
+{
+    fold_range(min, max, p, Vec::new, |mut acc, res| {
+        acc.push(res);
+        acc
+    })
+}
+
+/// Applies a parser of text zero or more times and joins the texts it returns
+/// The output borrows the input as long as each text is the input it was parsed from, like text
+/// without escapes, so it is only copied when a text differs.
+pub fn concat_any<'a, P>(p: P) -> impl Fn(&'a str) -> Parse<&'a str, Cow<'a, str>>
+where
+    P: Fn(&'a str) -> Parse<&'a str, Cow<'a, str>>,
+{
+    move |i: &'a str| {
+        fold_many(
+            sequence::serial(comb::position, &p),
+            || Cow::Borrowed(&i[..0]),
+            |text, (start, part): (&'a str, _)| {
+                let offset = i.len() - start.len();
+                match (text, part) {
+                    (Cow::Borrowed(text), Cow::Borrowed(part))
+                        if text.len() == offset && part.as_ptr() == start.as_ptr() =>
+                    {
+                        Cow::Borrowed(&i[..offset + part.len()])
+                    }
+                    (text, part) => Cow::Owned(text.into_owned() + &part),
+                }
+            },
+        )(i)
+    }
+}
+
+/// Applies a parser exactly `n` times, leaving what follows for the next parser
+pub fn count<I, O, P>(n: usize, p: P) -> impl Fn(I) -> Parse<I, Vec<O>>
+where
+    P: Fn(I) -> Parse<I, O>,
+    I: Copy,
+{
+    move |i: I| {
+        let mut list = Vec::with_capacity(n);
+        let mut rest = i;
+        while list.len() < n {
+            match p(rest) {
+                Parse::Success(res, sur) => {
+                    list.push(res);
+                    rest = sur;
+                }
+                Parse::Retreat(r) => return Parse::Retreat(r),
+                Parse::Halt(h) => return Parse::Halt(h),
+                Parse::Limit(Some(res), sur) if list.len() + 1 == n => {
+                    list.push(res);
+                    return Parse::Limit(Some(list), sur);
+                }
+                Parse::Limit(_, _) => return Parse::Limit(None, i),
+            }
+        }
+        Parse::Success(list, rest)
+    }
+}
+
+/// Whether a list may end with a separator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trailing {
+    /// A separator after the last item is left unconsumed
+    Forbid,
+    /// A separator after the last item is consumed if there
+    Allow,
+    /// Every item must be followed by a separator
+    Require,
+}
+
+/// Applies `item` `min` to `max` times with `separator` between, and returns a vector with the items
+/// Errors like `repeat_range`, and retreats if `Trailing::Require` and the last separator is missing.
+pub fn separated_range<I, O1, O2, P1, P2>(
+    min: usize,
+    max: usize,
+    trailing: Trailing,
+    separator: P1,
+    item: P2,
+) -> impl Fn(I) -> Parse<I, Vec<O2>>
+where
+    P1: Fn(I) -> Parse<I, O1>,
+    P2: Fn(I) -> Parse<I, O2>,
+    I: Input,
+{
+    move |i: I| {
+        let mut list = Vec::new();
+        // After the last item, and after its separator if any
+        let mut rest = i;
+        let mut rest_separated = i;
+        loop {
+            match item(rest_separated) {
+                Parse::Success(_, _) | Parse::Limit(Some(_), _) if list.len() == max => {
+                    return Parse::Halt(Error::new(&rest_separated, Expected::AtMost(max)))
+                }
+                Parse::Success(res, sur) => {
+                    list.push(res);
+                    rest = sur;
+                }
+                Parse::Retreat(r) => {
+                    let end = match trailing {
+                        Trailing::Forbid => rest,
+                        Trailing::Allow | Trailing::Require => rest_separated,
+                    };
+                    return match list.len() < min {
+                        true => Parse::Retreat(r),
+                        false => Parse::Success(list, end),
+                    };
+                }
+                Parse::Halt(h) => return Parse::Halt(h),
+                Parse::Limit(res, sur) => {
+                    let end = match res {
+                        Some(res) if trailing != Trailing::Require => {
+                            list.push(res);
+                            sur
+                        }
+                        Some(_) => return Parse::Limit(None, i),
+                        None if trailing == Trailing::Forbid => rest,
+                        None => rest_separated,
+                    };
+                    return match list.len() < min {
+                        true => Parse::Limit(None, i),
+                        false => Parse::Limit(Some(list), end),
+                    };
+                }
+            }
+            match separator(rest) {
+                Parse::Success(_, sur) => rest_separated = sur,
+                Parse::Retreat(r) if trailing == Trailing::Require => return Parse::Retreat(r),
+                Parse::Retreat(r) if list.len() < min => return Parse::Retreat(r),
+                Parse::Retreat(_) => return Parse::Success(list, rest),
+                Parse::Halt(h) => return Parse::Halt(h),
+                Parse::Limit(_, _) if trailing == Trailing::Require || list.len() < min => {
+                    return Parse::Limit(None, i)
+                }
+                Parse::Limit(_, _) => return Parse::Limit(Some(list), rest),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 
 mod tests {
@@ -98,7 +330,7 @@ mod tests {
             Parse::Limit(Some(vec!['a', 'a']), "")
         );
         assert_eq!(
-            repeat::repeat_any(comb::map_bool(str::take(2), |r| r.is_ascii()))("aabbåå"),
+            repeat::repeat_any(comb::map_bool(str::take(2), |r: &&str| r.is_ascii()))("aabbåå"),
             Parse::Success(vec!["aa", "bb"], "åå")
         );
         assert_eq!(
@@ -126,4 +358,100 @@ mod tests {
             Parse::Success(vec!["aa", "bb"], "cc")
         );
     }
+    #[test]
+    fn test_repeat_range() {
+        let digits = || repeat::repeat_range(2, 3, str::digit);
+        assert_eq!(digits()("12a"), Parse::Success(vec![1, 2], "a"));
+        assert_eq!(digits()("123a"), Parse::Success(vec![1, 2, 3], "a"));
+        assert!(digits()("1a").is_retreat());
+        assert_eq!(digits()("1"), Parse::Limit(None, "1"));
+        assert_eq!(digits()("12"), Parse::Limit(Some(vec![1, 2]), ""));
+        match digits()("1234") {
+            Parse::Halt(e) => {
+                assert_eq!(e.offset(&"1234"), 3);
+                assert_eq!(e.expected(), &[error::Expected::AtMost(3)]);
+            }
+            p => panic!("{p:?}"),
+        }
+    }
+    #[test]
+    fn test_count_and_fold() {
+        assert_eq!(
+            repeat::count(3, str::digit)("1234"),
+            Parse::Success(vec![1, 2, 3], "4")
+        );
+        assert!(repeat::count(3, str::digit)("12a").is_retreat());
+        assert_eq!(repeat::count(3, str::digit)("12"), Parse::Limit(None, "12"));
+        assert_eq!(
+            repeat::fold_many(str::digit, || 0u32, |a, d| a * 10 + d as u32)("123a"),
+            Parse::Success(123, "a")
+        );
+
+        // Successes that consume nothing end the repetition
+        let letters = || str::take_while(|c: char| c.is_alphabetic());
+        assert_eq!(
+            repeat::fold_many(letters(), || 0, |n, _| n + 1)("ab1"),
+            Parse::Success(1, "1")
+        );
+        assert!(repeat::repeat_range(2, 3, letters())("ab1").is_retreat());
+    }
+    #[test]
+    fn test_concat_any() {
+        use std::borrow::Cow;
+        let letters = || comb::map(str::take_some_while(char::is_alphabetic), Cow::Borrowed);
+        let escaped = || {
+            comb::map(sequence::preceded(str::char('\\'), str::pop), |c: char| {
+                Cow::Owned(c.to_string())
+            })
+        };
+        let text = repeat::concat_any(comb::either(letters(), escaped()));
+        match text("ab;") {
+            Parse::Success(Cow::Borrowed("ab"), ";") => (),
+            p => panic!("{p:?}"),
+        }
+        match text("ab\\;cd;") {
+            Parse::Success(Cow::Owned(text), ";") => assert_eq!(text, "ab;cd"),
+            p => panic!("{p:?}"),
+        }
+
+        // Texts that are each the input they were parsed from are borrowed together
+        let text = repeat::concat_any(comb::map(
+            comb::recognize(str::char_of("ab")),
+            Cow::Borrowed,
+        ));
+        match text("abba;") {
+            Parse::Success(Cow::Borrowed("abba"), ";") => (),
+            p => panic!("{p:?}"),
+        }
+    }
+
+    #[test]
+    fn test_separated_range() {
+        use repeat::Trailing;
+        let list = |trailing| repeat::separated_range(1, 3, trailing, str::char(','), str::digit);
+        assert_eq!(
+            list(Trailing::Forbid)("1,2,]"),
+            Parse::Success(vec![1, 2], ",]")
+        );
+        assert_eq!(
+            list(Trailing::Allow)("1,2,]"),
+            Parse::Success(vec![1, 2], "]")
+        );
+        assert_eq!(
+            list(Trailing::Allow)("1,2]"),
+            Parse::Success(vec![1, 2], "]")
+        );
+        assert_eq!(
+            list(Trailing::Require)("1,2,]"),
+            Parse::Success(vec![1, 2], "]")
+        );
+        assert!(list(Trailing::Require)("1,2]").is_retreat());
+        assert!(list(Trailing::Forbid)("]").is_retreat());
+        assert!(list(Trailing::Forbid)("1,2,3,4]").is_halt());
+        assert_eq!(
+            list(Trailing::Forbid)("1,2"),
+            Parse::Limit(Some(vec![1, 2]), "")
+        );
+        assert_eq!(list(Trailing::Require)("1,2"), Parse::Limit(None, "1,2"));
+    }
 }
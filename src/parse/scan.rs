@@ -0,0 +1,173 @@
+use super::input::{Input, Item};
+
+const ONES: u64 = 0x0101_0101_0101_0101;
+const HIGHS: u64 = 0x8080_8080_8080_8080;
+
+/// Sets the high bit of the bytes of `word` that are zero
+/// Bytes above the first zero byte may be set wrongly, but the lowest set bit is exact.
+#[inline]
+fn zero_bytes(word: u64) -> u64 {
+    word.wrapping_sub(ONES) & !word & HIGHS
+}
+
+/// Index of the first byte of `haystack` that is any of `needles`
+///
+/// Up to three needles are searched a word at a time, like `memchr`.
+pub fn find_any_byte(haystack: &[u8], needles: &[u8]) -> Option<usize> {
+    if needles.is_empty() {
+        return None;
+    }
+    if needles.len() > 3 {
+        let mut table = [false; 256];
+        for &n in needles {
+            table[n as usize] = true;
+        }
+        return haystack.iter().position(|&b| table[b as usize]);
+    }
+    let mut splats = [0; 3];
+    for (splat, &n) in splats.iter_mut().zip(needles) {
+        *splat = ONES * n as u64;
+    }
+    let splats = &splats[..needles.len()];
+    let mut chunks = haystack.chunks_exact(8);
+    let mut index = 0;
+    for chunk in &mut chunks {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap());
+        let found = splats
+            .iter()
+            .fold(0, |found, splat| found | zero_bytes(word ^ splat));
+        if found != 0 {
+            return Some(index + found.trailing_zeros() as usize / 8);
+        }
+        index += 8;
+    }
+    chunks
+        .remainder()
+        .iter()
+        .position(|b| needles.contains(b))
+        .map(|i| index + i)
+}
+
+/// Number of ASCII bytes at the start of `bytes`, counted a word at a time
+pub fn ascii_prefix_len(bytes: &[u8]) -> usize {
+    let mut chunks = bytes.chunks_exact(8);
+    let mut index = 0;
+    for chunk in &mut chunks {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap());
+        if word & HIGHS != 0 {
+            return index + (word & HIGHS).trailing_zeros() as usize / 8;
+        }
+        index += 8;
+    }
+    index
+        + chunks
+            .remainder()
+            .iter()
+            .position(|b| !b.is_ascii())
+            .unwrap_or(chunks.remainder().len())
+}
+
+/// Bytes of ASCII counted at once by `find_not`
+const WINDOW: usize = 64;
+
+/// Byte index of the first item not satisfying `f`
+///
+/// ASCII bytes are checked directly, and only other items are decoded from the input.
+pub fn find_not<I, F>(input: &I, f: F) -> Option<usize>
+where
+    I: Input,
+    F: Fn(I::Item) -> bool,
+{
+    let bytes = input.input_bytes();
+    let mut index = 0;
+    loop {
+        // ASCII is counted a window at a time, so the scan stops near the item it finds
+        let end = bytes.len().min(index + WINDOW);
+        let ascii = index + ascii_prefix_len(&bytes[index..end]);
+        if let Some(i) = bytes[index..ascii]
+            .iter()
+            .position(|&b| !f(I::Item::from_ascii(b)))
+        {
+            return Some(index + i);
+        }
+        index = ascii;
+        if ascii == end && end < bytes.len() {
+            continue;
+        }
+        let item = input.split_input(index).1.first_item()?;
+        if !f(item) {
+            return Some(index);
+        }
+        index += item.len_bytes();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn test_find_any_byte() {
+        let haystack = b"0123456789abcdef\"ghij\\";
+        assert_eq!(find_any_byte(haystack, b"\""), Some(16));
+        assert_eq!(find_any_byte(haystack, b"\\\"a"), Some(10));
+        assert_eq!(find_any_byte(haystack, b"\\"), Some(21));
+        assert_eq!(find_any_byte(haystack, b"xyz!"), None);
+        assert_eq!(find_any_byte(haystack, b"xyzj"), Some(20));
+        assert_eq!(find_any_byte(b"\x80\x01", b"\x01"), Some(1));
+        for len in 0..20 {
+            let mut haystack = vec![b'a'; len];
+            assert_eq!(find_any_byte(&haystack, b"\r\n"), None);
+            haystack.push(b'\n');
+            assert_eq!(find_any_byte(&haystack, b"\r\n"), Some(len));
+        }
+    }
+
+    #[test]
+    fn test_find_not() {
+        assert_eq!(ascii_prefix_len("abcdefghijå".as_bytes()), 10);
+        assert_eq!(ascii_prefix_len(b"abc"), 3);
+        let alpha = |c: char| c.is_alphabetic();
+        assert_eq!(find_not(&"abcdefghijåøæxyz1", alpha), Some(19));
+        assert_eq!(find_not(&"åøæ", alpha), None);
+        assert_eq!(find_not(&"ab€", alpha), Some(2));
+        assert_eq!(find_not(&&b"ab\xffc"[..], |b: u8| b != 0xff), Some(2));
+        let long = "a".repeat(100) + "å1";
+        assert_eq!(find_not(&long.as_str(), alpha), Some(102));
+        assert_eq!(find_not(&"a".repeat(64).as_str(), alpha), None);
+    }
+
+    /// Compares against scanning with `char_indices`, run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn bench_scan() {
+        let text = "lorem ipsum dolor sit amet, ".repeat(40_000) + "å\"";
+        let time = |name: &str, f: &dyn Fn() -> Option<usize>| {
+            let start = Instant::now();
+            let mut result = None;
+            for _ in 0..20 {
+                result = f();
+            }
+            eprintln!("{name}: {:?}", start.elapsed() / 20);
+            result
+        };
+        let naive = time("char_indices delimiter", &|| {
+            text.char_indices()
+                .find(|(_, c)| "\"\\".contains(*c))
+                .map(|(i, _)| i)
+        });
+        let fast = time("find_any_byte", &|| find_any_byte(text.as_bytes(), b"\"\\"));
+        assert_eq!(naive, fast);
+        let naive = time("char_indices predicate", &|| {
+            text.char_indices()
+                .find(|(_, c)| !(c.is_alphabetic() || " ,".contains(*c)))
+                .map(|(i, _)| i)
+        });
+        let fast = time("find_not", &|| {
+            find_not(&text.as_str(), |c| c.is_alphabetic() || " ,".contains(c))
+        });
+        assert_eq!(naive, fast);
+    }
+}
@@ -26,7 +26,7 @@ pub fn terminated<I, O1, O2, P1, P2>(p1: P1, p2: P2) -> impl Fn(I) -> Parse<I, O
 where
     P1: Fn(I) -> Parse<I, O1>,
     P2: Fn(I) -> Parse<I, O2>,
-    I: Copy + std::fmt::Display + std::fmt::Debug,
+    I: Copy,
 {
     map(serial(p1, p2), |(r1, _)| r1)
 }
@@ -35,7 +35,7 @@ pub fn preceded<I, O1, O2, P1, P2>(p1: P1, p2: P2) -> impl Fn(I) -> Parse<I, O2>
 where
     P1: Fn(I) -> Parse<I, O1>,
     P2: Fn(I) -> Parse<I, O2>,
-    I: Copy + std::fmt::Display + std::fmt::Debug,
+    I: Copy,
 {
     map(serial(p1, p2), |(_, r2)| r2)
 }
@@ -49,9 +49,9 @@ where
     P1: Fn(I) -> Parse<I, O1>,
     P2: Fn(I) -> Parse<I, O2>,
     P3: Fn(I) -> Parse<I, O3>,
-    I: Copy + std::fmt::Display + std::fmt::Debug,
+    I: Copy,
 {
-    map(serial3(before, subject, after), |(_, res, _)| res)
+    map(serial_of((before, subject, after)), |(_, res, _)| res)
 }
 
 pub fn around<I, O1, O2, O3, P1, P2, P3>(
@@ -63,39 +63,116 @@ where
     P1: Fn(I) -> Parse<I, O1>,
     P2: Fn(I) -> Parse<I, O2>,
     P3: Fn(I) -> Parse<I, O3>,
-    I: Copy + std::fmt::Display + std::fmt::Debug,
+    I: Copy,
 {
-    map(serial3(before, separator, after), |(b, _, a)| (b, a))
+    map(serial_of((before, separator, after)), |(b, _, a)| (b, a))
 }
 
-pub fn serial3<I, O1, O2, O3, P1, P2, P3>(
-    p1: P1,
-    p2: P2,
-    p3: P3,
-) -> impl Fn(I) -> Parse<I, (O1, O2, O3)>
-where
-    P1: Fn(I) -> Parse<I, O1>,
-    P2: Fn(I) -> Parse<I, O2>,
-    P3: Fn(I) -> Parse<I, O3>,
-    I: Copy + std::fmt::Display + std::fmt::Debug,
-{
-    map(serial(serial(p1, p2), p3), |((r1, r2), r3)| (r1, r2, r3))
+/// Parsers that can be applied in sequence, implemented for tuples of up to 12 parsers
+/// The outputs are collected in a flat tuple, see `serial!`
+pub trait Serial<I, O> {
+    fn parse_serial(&self, i: I) -> Parse<I, O>;
 }
-pub fn serial4<I, O1, O2, O3, O4, P1, P2, P3, P4>(
-    p1: P1,
-    p2: P2,
-    p3: P3,
-    p4: P4,
-) -> impl Fn(I) -> Parse<I, (O1, O2, O3, O4)>
+
+macro_rules! impl_serial {
+    ($($p:ident $o:ident),+ ; $last_p:ident $last_o:ident) => {
+        impl<I, $($p, $o,)+ $last_p, $last_o> Serial<I, ($($o,)+ $last_o)> for ($($p,)+ $last_p)
+        where
+            I: Copy,
+            $($p: Fn(I) -> Parse<I, $o>,)+
+            $last_p: Fn(I) -> Parse<I, $last_o>,
+        {
+            #[allow(non_snake_case)]
+            fn parse_serial(&self, i: I) -> Parse<I, ($($o,)+ $last_o)> {
+                let ($($p,)+ $last_p) = self;
+                let rest = i;
+                $(
+                    let ($o, rest) = match $p(rest) {
+                        Parse::Success(res, sur) => (res, sur),
+                        Parse::Retreat(r) => return Parse::Retreat(r),
+                        Parse::Halt(h) => return Parse::Halt(h),
+                        Parse::Limit(_, _) => return Parse::Limit(None, i),
+                    };
+                )+
+                match $last_p(rest) {
+                    Parse::Success(res, sur) => Parse::Success(($($o,)+ res), sur),
+                    Parse::Retreat(r) => Parse::Retreat(r),
+                    Parse::Halt(h) => Parse::Halt(h),
+                    Parse::Limit(Some(res), sur) => Parse::Limit(Some(($($o,)+ res)), sur),
+                    Parse::Limit(None, _) => Parse::Limit(None, i),
+                }
+            }
+        }
+    };
+}
+
+impl_serial!(P1 O1; P2 O2);
+impl_serial!(P1 O1, P2 O2; P3 O3);
+impl_serial!(P1 O1, P2 O2, P3 O3; P4 O4);
+impl_serial!(P1 O1, P2 O2, P3 O3, P4 O4; P5 O5);
+impl_serial!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5; P6 O6);
+impl_serial!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6; P7 O7);
+impl_serial!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7; P8 O8);
+impl_serial!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7, P8 O8; P9 O9);
+impl_serial!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7, P8 O8, P9 O9; P10 O10);
+impl_serial!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7, P8 O8, P9 O9, P10 O10; P11 O11);
+impl_serial!(P1 O1, P2 O2, P3 O3, P4 O4, P5 O5, P6 O6, P7 O7, P8 O8, P9 O9, P10 O10, P11 O11; P12 O12);
+
+/// Applies a tuple of parsers in sequence, of which all must succeed to gain `Parse::Success`
+pub fn serial_of<I, O, T>(parsers: T) -> impl Fn(I) -> Parse<I, O>
 where
-    P1: Fn(I) -> Parse<I, O1>,
-    P2: Fn(I) -> Parse<I, O2>,
-    P3: Fn(I) -> Parse<I, O3>,
-    P4: Fn(I) -> Parse<I, O4>,
-    I: Copy + std::fmt::Display + std::fmt::Debug,
+    T: Serial<I, O>,
 {
-    map(
-        serial(serial(p1, p2), serial(p3, p4)),
-        |((r1, r2), (r3, r4))| (r1, r2, r3, r4),
-    )
+    move |i: I| parsers.parse_serial(i)
+}
+
+/// Applies the parsers in sequence and returns their outputs in a flat tuple
+/// `serial!(p1, p2, p3)` parses `(O1, O2, O3)`
+#[macro_export]
+macro_rules! serial {
+    ($($p:expr),+ $(,)?) => {
+        $crate::parse::sequence::serial_of(($($p,)+))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::str::{char, digit, literal};
+    use super::*;
+
+    #[test]
+    fn test_serial_macro() {
+        let date = || serial!(digit, digit, char('-'), digit, digit);
+        assert_eq!(date()("12-31 "), Parse::Success((1, 2, '-', 3, 1), " "));
+        assert_eq!(date()("12-3"), Parse::Limit(None, "12-3"));
+        assert!(date()("1-231").is_retreat());
+        assert_eq!(
+            serial!(literal("a"), take_while_digit)("a12"),
+            Parse::Limit(Some(("a", "12")), "")
+        );
+        assert_eq!(
+            serial!(
+                char('a'),
+                char('b'),
+                char('c'),
+                char('d'),
+                char('e'),
+                char('f'),
+                char('g'),
+                char('h'),
+                char('i'),
+                char('j'),
+                char('k'),
+                char('l'),
+            )("abcdefghijklm"),
+            Parse::Success(
+                ('a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l'),
+                "m"
+            )
+        );
+    }
+
+    fn take_while_digit(i: &str) -> Parse<&str, &str> {
+        super::super::str::take_while(|c: char| c.is_ascii_digit())(i)
+    }
 }
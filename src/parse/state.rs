@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+use std::ops::DerefMut;
+
+use super::input::Input;
+use super::repeat::{self, Trailing};
+use super::{comb, recover, sequence, tools, trace, Parse};
+
+// Stateful versions of the combinators, for parsers of `Fn(&mut S, I) -> Parse<I, O>`
+//
+// The state carries configuration, like limits and flags, and collects results, like diagnostics.
+// Changes made to the state by a parser that retreats are kept.
+//
+// The combinators here run the stateless combinators of the same name, through `stateless`,
+// so they behave the same.
+
+/// Lifts a stateless parser, which ignores the state
+pub fn lift<S, I, O, P>(p: P) -> impl Fn(&mut S, I) -> Parse<I, O>
+where
+    P: Fn(I) -> Parse<I, O>,
+{
+    move |_: &mut S, i: I| p(i)
+}
+
+/// Runs a stateful parser as a stateless one, borrowing the state from `state` for each call
+/// This lets stateful parsers be used with any stateless combinator.
+pub fn stateless<'a, S, R, I, O, P>(
+    state: &'a RefCell<R>,
+    p: &'a P,
+) -> impl Fn(I) -> Parse<I, O> + 'a
+where
+    R: DerefMut<Target = S>,
+    P: Fn(&mut S, I) -> Parse<I, O>,
+{
+    move |i: I| p(&mut **state.borrow_mut(), i)
+}
+
+/// Shares the state with the stateless parsers `f` builds with `stateless`
+pub fn shared<S, T>(state: &mut S, f: impl FnOnce(&RefCell<&mut S>) -> T) -> T {
+    f(&RefCell::new(state))
+}
+
+/// See `comb::map`
+pub fn map<S, I, O, M, P, F>(p: P, func: F) -> impl Fn(&mut S, I) -> Parse<I, M>
+where
+    P: Fn(&mut S, I) -> Parse<I, O>,
+    F: Fn(O) -> M,
+    I: Copy,
+{
+    move |s: &mut S, i: I| shared(s, |s| comb::map(stateless(s, &p), &func)(i))
+}
+
+/// Maps the output with access to the state
+pub fn map_state<S, I, O, M, P, F>(p: P, func: F) -> impl Fn(&mut S, I) -> Parse<I, M>
+where
+    P: Fn(&mut S, I) -> Parse<I, O>,
+    F: Fn(&mut S, O) -> M,
+    I: Copy,
+{
+    move |s: &mut S, i: I| match p(s, i) {
+        Parse::Success(res, sur) => Parse::Success(func(s, res), sur),
+        Parse::Retreat(r) => Parse::Retreat(r),
+        Parse::Halt(h) => Parse::Halt(h),
+        Parse::Limit(Some(r), sur) => Parse::Limit(Some(func(s, r)), sur),
+        Parse::Limit(None, _) => Parse::Limit(None, i),
+    }
+}
+
+/// See `comb::either`
+pub fn either<S, I, O, P1, P2>(p1: P1, p2: P2) -> impl Fn(&mut S, I) -> Parse<I, O>
+where
+    P1: Fn(&mut S, I) -> Parse<I, O>,
+    P2: Fn(&mut S, I) -> Parse<I, O>,
+    I: Copy,
+{
+    move |s: &mut S, i: I| shared(s, |s| comb::either(stateless(s, &p1), stateless(s, &p2))(i))
+}
+
+/// See `comb::optional`
+pub fn optional<S, I, O, P>(p: P) -> impl Fn(&mut S, I) -> Parse<I, Option<O>>
+where
+    P: Fn(&mut S, I) -> Parse<I, O>,
+    I: Copy,
+{
+    move |s: &mut S, i: I| shared(s, |s| comb::optional(stateless(s, &p))(i))
+}
+
+/// See `sequence::serial`
+pub fn serial<S, I, O1, O2, P1, P2>(p1: P1, p2: P2) -> impl Fn(&mut S, I) -> Parse<I, (O1, O2)>
+where
+    P1: Fn(&mut S, I) -> Parse<I, O1>,
+    P2: Fn(&mut S, I) -> Parse<I, O2>,
+    I: Copy,
+{
+    move |s: &mut S, i: I| {
+        shared(s, |s| {
+            sequence::serial(stateless(s, &p1), stateless(s, &p2))(i)
+        })
+    }
+}
+
+/// See `sequence::preceded`
+pub fn preceded<S, I, O1, O2, P1, P2>(p1: P1, p2: P2) -> impl Fn(&mut S, I) -> Parse<I, O2>
+where
+    P1: Fn(&mut S, I) -> Parse<I, O1>,
+    P2: Fn(&mut S, I) -> Parse<I, O2>,
+    I: Copy,
+{
+    move |s: &mut S, i: I| {
+        shared(s, |s| {
+            sequence::preceded(stateless(s, &p1), stateless(s, &p2))(i)
+        })
+    }
+}
+
+/// See `sequence::terminated`
+pub fn terminated<S, I, O1, O2, P1, P2>(p1: P1, p2: P2) -> impl Fn(&mut S, I) -> Parse<I, O1>
+where
+    P1: Fn(&mut S, I) -> Parse<I, O1>,
+    P2: Fn(&mut S, I) -> Parse<I, O2>,
+    I: Copy,
+{
+    move |s: &mut S, i: I| {
+        shared(s, |s| {
+            sequence::terminated(stateless(s, &p1), stateless(s, &p2))(i)
+        })
+    }
+}
+
+/// See `sequence::between`
+pub fn between<S, I, O1, O2, O3, P1, P2, P3>(
+    before: P1,
+    subject: P2,
+    after: P3,
+) -> impl Fn(&mut S, I) -> Parse<I, O2>
+where
+    P1: Fn(&mut S, I) -> Parse<I, O1>,
+    P2: Fn(&mut S, I) -> Parse<I, O2>,
+    P3: Fn(&mut S, I) -> Parse<I, O3>,
+    I: Copy,
+{
+    move |s: &mut S, i: I| {
+        shared(s, |s| {
+            sequence::between(
+                stateless(s, &before),
+                stateless(s, &subject),
+                stateless(s, &after),
+            )(i)
+        })
+    }
+}
+
+/// See `repeat::repeat_any`
+pub fn repeat_any<S, I, O, P>(p: P) -> impl Fn(&mut S, I) -> Parse<I, Vec<O>>
+where
+    P: Fn(&mut S, I) -> Parse<I, O>,
+    I: Copy,
+{
+    move |s: &mut S, i: I| shared(s, |s| repeat::repeat_any(stateless(s, &p))(i))
+}
+
+/// See `repeat::separated_range`
+pub fn separated_range<S, I, O1, O2, P1, P2>(
+    min: usize,
+    max: usize,
+    trailing: Trailing,
+    separator: P1,
+    item: P2,
+) -> impl Fn(&mut S, I) -> Parse<I, Vec<O2>>
+where
+    P1: Fn(&mut S, I) -> Parse<I, O1>,
+    P2: Fn(&mut S, I) -> Parse<I, O2>,
+    I: Input,
+{
+    move |s: &mut S, i: I| {
+        shared(s, |s| {
+            repeat::separated_range(
+                min,
+                max,
+                trailing,
+                stateless(s, &separator),
+                stateless(s, &item),
+            )(i)
+        })
+    }
+}
+
+/// See `tools::halt`
+pub fn halt<S, I, O, P>(p: P) -> impl Fn(&mut S, I) -> Parse<I, O>
+where
+    P: Fn(&mut S, I) -> Parse<I, O>,
+{
+    move |s: &mut S, i: I| shared(s, |s| tools::halt(stateless(s, &p))(i))
+}
+
+/// See `tools::label`
+pub fn label<S, I, O, P>(p: P, label: &'static str) -> impl Fn(&mut S, I) -> Parse<I, O>
+where
+    P: Fn(&mut S, I) -> Parse<I, O>,
+    I: Input,
+{
+    move |s: &mut S, i: I| shared(s, |s| tools::label(stateless(s, &p), label)(i))
+}
+
+/// See `recover::recover_with`
+pub fn recover_with<S, I, O, O2, P, Q>(
+    p: P,
+    sync: Q,
+    placeholder: O,
+) -> impl Fn(&mut S, I) -> Parse<I, O>
+where
+    P: Fn(&mut S, I) -> Parse<I, O>,
+    Q: Fn(I) -> Parse<I, O2>,
+    I: Input,
+    O: Clone,
+{
+    move |s: &mut S, i: I| {
+        shared(s, |s| {
+            recover::recover_with(stateless(s, &p), &sync, placeholder.clone())(i)
+        })
+    }
+}
+
+/// See `trace::trace`
+pub fn trace<S, I, O, P>(name: &'static str, p: P) -> impl Fn(&mut S, I) -> Parse<I, O>
+where
+    P: Fn(&mut S, I) -> Parse<I, O>,
+    I: Input,
+{
+    move |s: &mut S, i: I| shared(s, |s| trace::trace(name, stateless(s, &p))(i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::error::{Error, Expected};
+    use super::super::str::{char, digit};
+    use super::*;
+
+    /// Nested lists of digits, limited in depth, counting the digits
+    #[derive(Default)]
+    struct Limits {
+        depth: usize,
+        digits: usize,
+    }
+
+    fn list<'a>(s: &mut Limits, i: &'a str) -> Parse<&'a str, usize> {
+        preceded(lift(char('(')), terminated(items, lift(char(')'))))(s, i)
+    }
+
+    fn items<'a>(s: &mut Limits, i: &'a str) -> Parse<&'a str, usize> {
+        if s.depth == 2 {
+            return Parse::Halt(Error::new(&i, Expected::Label("shallower list")));
+        }
+        s.depth += 1;
+        let result = map(
+            repeat_any(either(
+                map_state(lift(digit), |s: &mut Limits, _| s.digits += 1),
+                map(list, |_| ()),
+            )),
+            |items| items.len(),
+        )(s, i);
+        s.depth -= 1;
+        result
+    }
+
+    #[test]
+    fn test_state() {
+        let mut limits = Limits::default();
+        assert_eq!(list(&mut limits, "(1(23)4)."), Parse::Success(3, "."));
+        assert_eq!((limits.depth, limits.digits), (0, 4));
+        assert!(list(&mut limits, "(1((2)))").is_halt());
+
+        let pair = shared(&mut limits, |s| {
+            sequence::serial(stateless(s, &list), char(';'))("(5);")
+        });
+        assert_eq!(pair, Parse::Success((1, ';'), ""));
+        assert_eq!(limits.digits, 6);
+    }
+}
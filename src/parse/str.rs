@@ -1,195 +1,262 @@
+use super::class::CharClass;
 use super::comb::{map_option, map_result};
+use super::error::{Error, Expected};
+use super::input::{Input, Item, ItemSet, Tag};
+use super::scan::{find_any_byte, find_not};
+use super::tools::label;
 use super::Parse;
 
-const ASCII_ALPHABET: &'static str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const ASCII_ALPHABET: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
 
-pub fn alpha_char<'a>(i: &'a str) -> impl Fn(&'a str) -> Parse<&'a str, char> {
+pub fn alpha_char<I: Input>(i: I) -> Parse<I, I::Item> {
     map_result(
         pop,
-        |c| match ASCII_ALPHABET.contains(c) {
+        |c: I::Item| match ASCII_ALPHABET.contains(c.as_char()) {
             true => Ok(c),
             false => Err(()),
         },
-        "is not in alphabet",
-    )
-}
-
-pub fn pop(input: &str) -> Parse<&str, char> {
-    let mut iter = input.char_indices();
-    match iter.next() {
-        Some((_, c)) => {
-            if let Some((next, _)) = iter.next() {
-                Parse::Success(c, &input[next..])
-            } else {
-                Parse::Success(c, "")
-            }
-        }
+        "letter",
+    )(i)
+}
+
+pub fn pop<I: Input>(input: I) -> Parse<I, I::Item> {
+    match input.first_item() {
+        Some(c) => Parse::Success(c, input.split_input(c.len_bytes()).1),
         None => Parse::Limit(None, input),
     }
 }
 
-pub fn peek_char(input: &str) -> Parse<&str, char> {
-    match input.chars().next() {
+pub fn peek_char<I: Input>(input: I) -> Parse<I, I::Item> {
+    match input.first_item() {
         Some(c) => Parse::Success(c, input),
         None => Parse::Limit(None, input),
     }
 }
 
-pub fn other_than(chars: &'static str) -> impl Fn(&str) -> Parse<&str, &str> {
-    move |input: &str| {
-        let index = {
-            let mut index = 0;
-            let mut iter = input.char_indices();
-            loop {
-                if let Some((i, c)) = iter.next() {
-                    index = i;
-                    if chars.contains(c) {
-                        break;
-                    }
-                } else {
-                    return Parse::Limit(Some(&input[0..index]), &input[index..]);
-                }
-            }
-
-            index
+pub fn other_than<I, S>(chars: S) -> impl Fn(I) -> Parse<I, I>
+where
+    I: Input,
+    S: ItemSet<I::Item>,
+{
+    move |input: I| {
+        let found = match chars.ascii_bytes() {
+            Some(bytes) => find_any_byte(input.input_bytes(), bytes),
+            None => find_not(&input, |c| !chars.contains_item(c)),
+        };
+        let index = match found {
+            Some(index) => index,
+            None if input.is_input_empty() => return Parse::Limit(None, input),
+            None => return Parse::Limit(Some(input), input.end()),
         };
         match index {
-            0 => Parse::Retreat("hur".to_string()),
+            0 => Parse::Retreat(Error::new(&input, Expected::NoneOf(chars.describe()))),
             _ => {
-                let res = &input[0..index];
-                let sur = &input[index..];
+                let (res, sur) = input.split_input(index);
                 Parse::Success(res, sur)
             }
         }
     }
 }
 
-pub fn take(num: usize) -> impl Fn(&str) -> Parse<&str, &str> {
-    move |input: &str| {
-        let mut index = 0;
+pub fn take<I: Input>(num: usize) -> impl Fn(I) -> Parse<I, I> {
+    move |input: I| {
         let mut count = 0;
-        let mut iter = input.char_indices();
-        loop {
-            if let Some((i, _)) = iter.next() {
-                index = i;
-            } else {
-                return if num == count {
-                    Parse::Success(input, "")
-                } else {
-                    if count > 0 {
-                        Parse::Limit(Some(input), "")
-                    } else {
-                        Parse::Limit(None, "")
-                    }
-                };
-            }
+        for (index, _) in input.item_indices() {
             if count == num {
-                break;
+                let (res, sur) = input.split_input(index);
+                return Parse::Success(res, sur);
             }
-
             count += 1;
         }
-
-        let res = &input[0..index];
-        let sur = &input[index..];
-        println!("take: num: {num}, res: {res}, sur: {sur}");
-        Parse::Success(res, sur)
+        if num == count {
+            Parse::Success(input, input.end())
+        } else if count > 0 {
+            Parse::Limit(Some(input), input.end())
+        } else {
+            Parse::Limit(None, input.end())
+        }
     }
 }
 
-pub fn take_while<F>(f: F) -> impl Fn(&str) -> Parse<&str, &str>
+pub fn take_while<I, F>(f: F) -> impl Fn(I) -> Parse<I, I>
 where
-    F: Fn(char) -> bool,
+    I: Input,
+    F: Fn(I::Item) -> bool,
 {
-    move |input: &str| {
-        let mut index = 0;
-        let mut iter = input.char_indices();
-        loop {
-            if let Some((i, c)) = iter.next() {
-                index = i;
-                if !f(c) {
-                    break;
-                }
-            } else {
-                return Parse::Limit(Some(input), "");
-            }
+    move |input: I| match find_not(&input, &f) {
+        Some(index) => {
+            let (res, sur) = input.split_input(index);
+            Parse::Success(res, sur)
         }
-        let res = &input[0..index];
-        let sur = &input[index..];
-        Parse::Success(res, sur)
+        None => Parse::Limit(Some(input), input.end()),
     }
 }
 
-pub fn take_some_while<F>(f: F) -> impl Fn(&str) -> Parse<&str, &str>
+pub fn take_some_while<I, F>(f: F) -> impl Fn(I) -> Parse<I, I>
 where
-    F: Fn(char) -> bool,
+    I: Input,
+    F: Fn(I::Item) -> bool,
     F: Copy,
 {
-    move |input: &str| {
-        if let Some((i, c)) = input.char_indices().next() {
-            if f(c) {
-                take_while(f)(&input[i..])
-            } else {
-                Parse::Retreat("Take some requires at least one match".to_string())
+    move |input: I| match input.first_item() {
+        Some(c) if f(c) => take_while(f)(input),
+        Some(_) => Parse::Retreat(Error::new(&input, Expected::Predicate)),
+        None => Parse::Limit(None, input),
+    }
+}
+
+/// Matches the input against `expected`, which can be a `&str` for both `&str` and `&[u8]` inputs
+/// Limits if the input ends before the literal could be decided
+pub fn literal<I, T>(expected: T) -> impl Fn(I) -> Parse<I, I>
+where
+    I: Input,
+    T: Tag<I>,
+{
+    move |input: I| match expected.match_start(&input) {
+        Some(len) => {
+            let (res, sur) = input.split_input(len);
+            Parse::Success(res, sur)
+        }
+        None if expected.is_prefixed_by(&input) => Parse::Limit(None, input),
+        None => Parse::Retreat(Error::new(&input, expected.expected())),
+    }
+}
+
+/// Matches the input against `expected` ignoring ASCII case, and returns the input as spelled
+/// Limits if the input ends before the literal could be decided
+pub fn literal_ci<I: Input>(expected: &'static str) -> impl Fn(I) -> Parse<I, I> {
+    move |input: I| {
+        let (bytes, tag) = (input.input_bytes(), expected.as_bytes());
+        match bytes.len() < tag.len() {
+            false if bytes[..tag.len()].eq_ignore_ascii_case(tag) => {
+                let (res, sur) = input.split_input(tag.len());
+                Parse::Success(res, sur)
             }
-        } else {
-            Parse::Limit(None, input)
+            true if tag[..bytes.len()].eq_ignore_ascii_case(bytes) => Parse::Limit(None, input),
+            _ => Parse::Retreat(Error::new(&input, Expected::Literal(expected))),
         }
     }
 }
 
-pub fn literal(expected: &'static str) -> impl Fn(&str) -> Parse<&str, &str> {
-    move |input: &str| match input.get(0..expected.len()) {
-        Some(next) if next == expected => {
-            let remaining = &input[expected.len()..];
-            Parse::Success(expected, remaining)
+/// Matches the longest of the keywords ignoring ASCII case, and returns it as spelled in the table
+/// Limits if the input ends while a longer keyword could still match
+pub fn one_of_keywords_ci<I: Input>(
+    keywords: &'static [&'static str],
+) -> impl Fn(I) -> Parse<I, &'static str> {
+    move |input: I| {
+        let bytes = input.input_bytes();
+        let mut found: Option<&'static str> = None;
+        let mut undecided = false;
+        for &keyword in keywords {
+            let tag = keyword.as_bytes();
+            if bytes.len() < tag.len() {
+                undecided |= tag[..bytes.len()].eq_ignore_ascii_case(bytes);
+            } else if bytes[..tag.len()].eq_ignore_ascii_case(tag)
+                && found.is_none_or(|f| f.len() < tag.len())
+            {
+                found = Some(keyword);
+            }
+        }
+        match (found, undecided) {
+            (Some(k), false) => Parse::Success(k, input.split_input(k.len()).1),
+            (Some(k), true) => Parse::Limit(Some(k), input.split_input(k.len()).1),
+            (None, true) => Parse::Limit(None, input),
+            (None, false) => Parse::Retreat(
+                keywords
+                    .iter()
+                    .map(|k| Error::new(&input, Expected::Literal(k)))
+                    .reduce(Error::merge)
+                    .unwrap_or_else(|| Error::new(&input, Expected::Label("keyword"))),
+            ),
         }
-        _ => Parse::Retreat(format!("Expected '{}', found '{}'", expected, input)),
     }
 }
 
-pub fn char<'a>(char: char) -> impl Fn(&'a str) -> Parse<&str, char> {
-    move |input: &str| -> Parse<&str, char> {
-        match input.char_indices().next() {
-            Some((i, c)) => match c == char {
-                true => {
-                    // let consumed = input.get(..i + 1).unwrap();
-                    let remaining = input.get(i + 1..).unwrap();
-                    Parse::Success(char, remaining)
-                }
-                false => Parse::Retreat(format!("char didnt match for: {input}, expected {char}")),
+pub fn char<I: Input>(char: I::Item) -> impl Fn(I) -> Parse<I, I::Item> {
+    move |input: I| -> Parse<I, I::Item> {
+        match input.first_item() {
+            Some(c) => match c == char {
+                true => Parse::Success(char, input.split_input(c.len_bytes()).1),
+                false => Parse::Retreat(Error::new(&input, Expected::Char(char.as_char()))),
             },
             None => Parse::Limit(None, input),
         }
     }
 }
 
-pub fn char_of(chars: &'static str) -> impl Fn(&str) -> Parse<&str, char> {
-    move |i: &str| {
-        map_result(
-            pop,
-            |c| match chars.contains(c) {
-                true => Ok(c),
-                false => Err(()),
-            },
-            "start with non-zero",
-        )(i)
+/// Matches the character ignoring ASCII case, and returns the item as found
+pub fn char_ci<I: Input>(char: char) -> impl Fn(I) -> Parse<I, I::Item> {
+    move |input: I| match input.first_item() {
+        Some(c) if c.as_char().eq_ignore_ascii_case(&char) => {
+            Parse::Success(c, input.split_input(c.len_bytes()).1)
+        }
+        Some(_) => Parse::Retreat(Error::new(&input, Expected::Char(char))),
+        None => Parse::Limit(None, input),
     }
 }
 
-pub fn some_chars_of(chars: &'static str) -> impl Fn(&str) -> Parse<&str, &str> {
-    take_some_while(|c| chars.contains(c))
+pub fn char_of<I, S>(chars: S) -> impl Fn(I) -> Parse<I, I::Item>
+where
+    I: Input,
+    S: ItemSet<I::Item>,
+{
+    move |input: I| match input.first_item() {
+        Some(c) if chars.contains_item(c) => Parse::Success(c, input.split_input(c.len_bytes()).1),
+        Some(_) => Parse::Retreat(Error::new(&input, Expected::OneOf(chars.describe()))),
+        None => Parse::Limit(None, input),
+    }
 }
 
-/// Parses one digit, 0123456789
-pub fn digit(input: &str) -> Parse<&str, u8> {
-    map_option(pop, |c| {
-        if (c as u8) > 0x2F && (c as u8) < 0x3A {
-            Some(c as u8 - 48)
-        } else {
-            None
+pub fn some_chars_of<I, S>(chars: S) -> impl Fn(I) -> Parse<I, I>
+where
+    I: Input,
+    S: ItemSet<I::Item>,
+{
+    move |input: I| match take_some_while(|c| chars.contains_item(c))(input) {
+        Parse::Retreat(_) => Parse::Retreat(Error::new(&input, Expected::OneOf(chars.describe()))),
+        p => p,
+    }
+}
+
+/// Parses one character of the class
+pub fn class_char<I: Input>(class: &CharClass) -> impl Fn(I) -> Parse<I, I::Item> + '_ {
+    move |input: I| match input.first_item() {
+        Some(c) if class.contains(c.as_char()) => {
+            Parse::Success(c, input.split_input(c.len_bytes()).1)
+        }
+        Some(_) => Parse::Retreat(Error::new(&input, Expected::Label(class.name()))),
+        None => Parse::Limit(None, input),
+    }
+}
+
+/// Parses one or more characters of the class
+pub fn take_class<I: Input>(class: &CharClass) -> impl Fn(I) -> Parse<I, I> + '_ {
+    move |input: I| match find_not(&input, |c| class.contains(c.as_char())) {
+        Some(0) => Parse::Retreat(Error::new(&input, Expected::Label(class.name()))),
+        Some(index) => {
+            let (res, sur) = input.split_input(index);
+            Parse::Success(res, sur)
         }
-    })(input)
+        None if input.is_input_empty() => Parse::Limit(None, input),
+        None => Parse::Limit(Some(input), input.end()),
+    }
+}
+
+/// Succeeds at the end of the input
+/// The empty input gives `Limit(Some(()))`, since more input may still come when streaming.
+pub fn eof<I: Input>(input: I) -> Parse<I, ()> {
+    match input.is_input_empty() {
+        true => Parse::Limit(Some(()), input),
+        false => Parse::Retreat(Error::new(&input, Expected::End)),
+    }
+}
+
+/// Parses one digit, 0123456789
+pub fn digit<I: Input>(input: I) -> Parse<I, u8> {
+    label(
+        map_option(pop, |c: I::Item| c.as_char().to_digit(10).map(|d| d as u8)),
+        "digit",
+    )(input)
 }
 
 #[cfg(test)]
@@ -210,4 +277,91 @@ mod tests {
         assert!(digit(":").is_retreat());
         assert!(digit("˚").is_retreat());
     }
+    #[test]
+    fn test_bytes() {
+        let input: &[u8] = b"GET / HTTP/1.1\r\n";
+        assert_eq!(
+            take_while(|b: u8| b.is_ascii_alphabetic())(input),
+            Parse::Success(&b"GET"[..], &b" / HTTP/1.1\r\n"[..])
+        );
+        assert_eq!(
+            other_than(" \r")(&input[4..]),
+            Parse::Success(&b"/"[..], &b" HTTP/1.1\r\n"[..])
+        );
+        assert_eq!(
+            literal("HTTP/")(&input[6..]),
+            Parse::Success(&b"HTTP/"[..], &b"1.1\r\n"[..])
+        );
+        assert_eq!(literal("HTTP/")(&b"HT"[..]), Parse::Limit(None, &b"HT"[..]));
+        assert_eq!(char(b'G')(input), Parse::Success(b'G', &input[1..]));
+        assert_eq!(take(3)(input), Parse::Success(&b"GET"[..], &input[3..]));
+    }
+    #[test]
+    fn test_class() {
+        const HEX: CharClass = CharClass::range('0', '9')
+            .union(CharClass::range('a', 'f'))
+            .named("hex digit");
+        assert_eq!(take_class(&HEX)("c0ffee!"), Parse::Success("c0ffee", "!"));
+        assert_eq!(
+            take_class(&HEX)(&b"ab"[..]),
+            Parse::Limit(Some(&b"ab"[..]), &b""[..])
+        );
+        assert_eq!(
+            take_class(&HEX)("g"),
+            Parse::Retreat(Error::new(&"g", Expected::Label("hex digit")))
+        );
+        assert_eq!(class_char(&HEX)("ab"), Parse::Success('a', "b"));
+        assert_eq!(class_char(&HEX)(""), Parse::Limit(None, ""));
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        assert_eq!(literal_ci("http")("HTtp:"), Parse::Success("HTtp", ":"));
+        assert_eq!(
+            literal_ci("http")(&b"HT"[..]),
+            Parse::Limit(None, &b"HT"[..])
+        );
+        assert!(literal_ci("http")("htp").is_retreat());
+        assert_eq!(char_ci('x')("X1"), Parse::Success('X', "1"));
+        assert_eq!(char_ci('x')(&b"x"[..]), Parse::Success(b'x', &b""[..]));
+        assert!(char_ci('x')("y").is_retreat());
+
+        let keyword = one_of_keywords_ci(&["in", "int", "if"]);
+        assert_eq!(keyword("IF x"), Parse::Success("if", " x"));
+        assert_eq!(keyword("Int x"), Parse::Success("int", " x"));
+        assert_eq!(keyword("iN"), Parse::Limit(Some("in"), ""));
+        assert_eq!(keyword("I"), Parse::Limit(None, "I"));
+        match keyword("on") {
+            Parse::Retreat(e) => assert_eq!(e.expected().len(), 3),
+            p => panic!("{p:?}"),
+        }
+    }
+
+    #[test]
+    fn test_eof() {
+        assert_eq!(eof(""), Parse::Limit(Some(()), ""));
+        assert_eq!(eof(&b""[..]), Parse::Limit(Some(()), &b""[..]));
+        assert_eq!(eof("a"), Parse::Retreat(Error::new(&"a", Expected::End)));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            char('a')("b"),
+            Parse::Retreat(Error::new(&"b", Expected::Char('a')))
+        );
+        assert_eq!(
+            literal("null")(" null"),
+            Parse::Retreat(Error::new(&" null", Expected::Literal("null")))
+        );
+        match digit("x") {
+            Parse::Retreat(e) => assert_eq!(e.expected(), &[Expected::Label("digit")]),
+            p => panic!("{p:?}"),
+        }
+    }
+    #[test]
+    fn test_char_multibyte() {
+        assert_eq!(char('å')("åb"), Parse::Success('å', "b"));
+        assert_eq!(other_than("\"")("abc"), Parse::Limit(Some("abc"), ""));
+    }
 }
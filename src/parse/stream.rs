@@ -0,0 +1,309 @@
+use super::error::{Error, Expected};
+use super::input::Input;
+use super::Parse;
+
+/// Inputs that can be viewed directly from the bytes buffered by a `Stream`
+pub trait FromBytes<'a>: Input + 'a {
+    /// Views the bytes as input
+    /// May leave out an incomplete item at the end, which is then parsed when more bytes arrive
+    fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error>;
+}
+
+impl<'a> FromBytes<'a> for &'a [u8] {
+    fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error> {
+        Ok(bytes)
+    }
+}
+
+impl<'a> FromBytes<'a> for &'a str {
+    fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error> {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            // The chunk ended in the middle of a character
+            Err(e) if e.error_len().is_none() => {
+                Ok(std::str::from_utf8(&bytes[..e.valid_up_to()]).expect("prefix is valid UTF-8"))
+            }
+            Err(e) => Err(Error::new(
+                &&bytes[e.valid_up_to()..],
+                Expected::Label("UTF-8"),
+            )),
+        }
+    }
+}
+
+/// Incremental parse driver
+///
+/// Owns a buffer of the bytes that are not parsed yet, which is fed chunks as they are read.
+/// Every call to `parse` yields the next output if the buffer holds a complete one.
+/// When the parser limits, or succeeds without consuming anything, the parser is not run again
+/// until more bytes are fed, and the bytes consumed by earlier outputs are dropped from the buffer
+/// when the next chunk arrives.
+#[derive(Debug, Default)]
+pub struct Stream {
+    buffer: Vec<u8>,
+    /// Start of the unparsed bytes in `buffer`
+    start: usize,
+    /// Number of bytes dropped from the buffer so far
+    dropped: usize,
+    /// Number of bytes at the end of the buffer that were left out of the last parse, since they
+    /// are the start of a character
+    held: usize,
+    /// The parser limited or made no progress, and no bytes have been fed since
+    starved: bool,
+    /// No more bytes will be fed
+    ended: bool,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Stream::default()
+    }
+
+    /// Appends a chunk to the buffer
+    pub fn feed(&mut self, chunk: &[u8]) {
+        if self.start > 0 {
+            self.buffer.drain(..self.start);
+            self.dropped += self.start;
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(chunk);
+        self.starved = chunk.is_empty() && self.starved;
+    }
+
+    /// Marks that no more bytes will be fed, so limits are accepted as the final parse
+    pub fn end(&mut self) {
+        self.ended = true;
+        self.starved = false;
+    }
+
+    /// Runs the parser on the buffered bytes
+    /// Returns `Ok(None)` if more bytes are needed to complete the output, or if the stream has ended and is empty
+    /// Expects the same parser to be given until it produces an output, since a limit is remembered until the next feed
+    pub fn parse<O, P>(&mut self, parser: P) -> Result<Option<O>, Error>
+    where
+        P: for<'a> Fn(&'a [u8]) -> Parse<&'a [u8], O>,
+    {
+        self.run(parser)
+    }
+
+    /// Like `parse`, but for parsers of text
+    /// A character split between two chunks is parsed when its last byte arrives. If the stream
+    /// ends before it does, the bytes are an error.
+    pub fn parse_str<O, P>(&mut self, parser: P) -> Result<Option<O>, Error>
+    where
+        P: for<'a> Fn(&'a str) -> Parse<&'a str, O>,
+    {
+        self.run(parser)
+    }
+
+    fn run<'a, I, O, P>(&'a mut self, parser: P) -> Result<Option<O>, Error>
+    where
+        I: FromBytes<'a>,
+        P: Fn(I) -> Parse<I, O>,
+    {
+        if self.starved {
+            return Ok(None);
+        }
+        let bytes = &self.buffer[self.start..];
+        self.held = 0;
+        let input = I::from_bytes(bytes)?;
+        let held = bytes.len() - input.input_len();
+        // The rest of a character that was cut off won't arrive
+        let cut = || {
+            Err(Error::new(
+                &&bytes[input.input_len()..],
+                Expected::Label("UTF-8"),
+            ))
+        };
+        if self.ended && held > 0 && input.is_input_empty() {
+            return cut();
+        }
+        let (res, sur) = match parser(input) {
+            Parse::Success(res, sur) => (res, sur),
+            Parse::Limit(Some(res), sur) if self.ended => (res, sur),
+            Parse::Limit(None, _) if self.ended && held > 0 => return cut(),
+            Parse::Limit(None, _) if self.ended && input.is_input_empty() => return Ok(None),
+            Parse::Limit(_, _) if self.ended => {
+                self.held = held;
+                return Err(Error::new(&input.end(), Expected::More));
+            }
+            Parse::Limit(_, _) => {
+                self.starved = true;
+                return Ok(None);
+            }
+            Parse::Retreat(e) | Parse::Halt(e) => {
+                self.held = held;
+                return Err(e);
+            }
+        };
+        let consumed = input.input_len() - sur.input_len();
+        // Running the parser again on the same bytes would give the same output forever
+        self.starved = consumed == 0;
+        self.start += consumed;
+        Ok(Some(res))
+    }
+
+    /// Number of bytes fed so far
+    pub fn fed(&self) -> usize {
+        self.dropped + self.buffer.len()
+    }
+
+    /// The bytes that are not parsed yet
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer[self.start..]
+    }
+
+    pub fn into_buffered(mut self) -> Vec<u8> {
+        self.buffer.drain(..self.start);
+        self.buffer
+    }
+
+    /// Byte offset from the start of the stream to where the error occurred
+    /// Only valid for an error returned from the last call to `parse`
+    pub fn offset(&self, error: &Error) -> usize {
+        let parsed = self.buffered().len() - self.held;
+        self.dropped + self.start + error.offset(&&self.buffered()[..parsed])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sequence::{preceded, terminated};
+    use super::super::str::{char, other_than, take_while};
+    use super::*;
+
+    fn word(i: &[u8]) -> Parse<&[u8], Vec<u8>> {
+        match terminated(other_than(" "), char(b' '))(i) {
+            Parse::Success(w, s) => Parse::Success(w.to_vec(), s),
+            Parse::Limit(_, _) => Parse::Limit(None, i),
+            Parse::Retreat(e) => Parse::Retreat(e),
+            Parse::Halt(e) => Parse::Halt(e),
+        }
+    }
+
+    #[test]
+    fn test_feed() {
+        let mut stream = Stream::new();
+        stream.feed(b"he");
+        assert_eq!(stream.parse(word), Ok(None));
+        stream.feed(b"llo wo");
+        assert_eq!(stream.parse(word), Ok(Some(b"hello".to_vec())));
+        assert_eq!(stream.parse(word), Ok(None));
+        assert_eq!(stream.buffered(), b"wo");
+        stream.feed(b"rld ");
+        assert_eq!(stream.buffer, b"world ");
+        assert_eq!(stream.fed(), 12);
+        assert_eq!(stream.parse(word), Ok(Some(b"world".to_vec())));
+        assert_eq!(stream.parse(word), Ok(None));
+    }
+
+    #[test]
+    fn test_starved() {
+        let calls = std::cell::Cell::new(0);
+        let mut stream = Stream::new();
+        stream.feed(b"ab");
+        for _ in 0..2 {
+            let result = stream.parse(|i: &[u8]| {
+                calls.set(calls.get() + 1);
+                word(i)
+            });
+            assert_eq!(result, Ok(None));
+        }
+        assert_eq!(calls.get(), 1);
+        stream.feed(b" ");
+        assert_eq!(stream.parse(word), Ok(Some(b"ab".to_vec())));
+    }
+
+    fn number(i: &str) -> Parse<&str, String> {
+        match preceded(
+            take_while(|c| c == ' '),
+            take_while(|c: char| c.is_ascii_digit()),
+        )(i)
+        {
+            Parse::Success(d, s) => Parse::Success(d.to_string(), s),
+            Parse::Limit(d, s) => Parse::Limit(d.map(|d| d.to_string()), s),
+            Parse::Retreat(e) => Parse::Retreat(e),
+            Parse::Halt(e) => Parse::Halt(e),
+        }
+    }
+
+    #[test]
+    fn test_end() {
+        let mut stream = Stream::new();
+        stream.feed(b"12 34");
+        assert_eq!(stream.parse_str(number), Ok(Some("12".to_string())));
+        assert_eq!(stream.parse_str(number), Ok(None));
+        stream.end();
+        assert_eq!(stream.parse_str(number), Ok(Some("34".to_string())));
+        assert_eq!(stream.parse_str(number), Ok(None));
+    }
+
+    #[test]
+    fn test_utf8_split() {
+        let mut stream = Stream::new();
+        let bytes = "å ".as_bytes();
+        stream.feed(&bytes[..1]);
+        fn c(i: &str) -> Parse<&str, char> {
+            terminated(char('å'), char(' '))(i)
+        }
+        assert_eq!(stream.parse_str(c), Ok(None));
+        stream.feed(&bytes[1..]);
+        assert_eq!(stream.parse_str(c), Ok(Some('å')));
+    }
+
+    #[test]
+    fn test_utf8_offset() {
+        fn c(i: &str) -> Parse<&str, char> {
+            terminated(char('å'), char(' '))(i)
+        }
+        // Errors are placed in the bytes as they were fed, before a character that is cut off
+        let mut stream = Stream::new();
+        let bytes = "å øx".as_bytes();
+        stream.feed(&bytes[..4]);
+        assert_eq!(stream.parse_str(c), Ok(Some('å')));
+        stream.feed(&bytes[4..]);
+        stream.feed(&"æ".as_bytes()[..1]);
+        let error = stream.parse_str(c).unwrap_err();
+        assert_eq!(stream.offset(&error), 3);
+
+        // A character that is cut off when the stream ends is an error
+        let mut stream = Stream::new();
+        stream.feed(&"å å".as_bytes()[..4]);
+        stream.end();
+        assert_eq!(stream.parse_str(c), Ok(Some('å')));
+        let error = stream.parse_str(c).unwrap_err();
+        assert_eq!(error.expected(), &[Expected::Label("UTF-8")]);
+        assert_eq!(stream.offset(&error), 3);
+    }
+
+    #[test]
+    fn test_progress() {
+        let calls = std::cell::Cell::new(0);
+        let parse = |stream: &mut Stream| {
+            stream.parse(|i: &[u8]| {
+                calls.set(calls.get() + 1);
+                Parse::Success((), i)
+            })
+        };
+        let mut stream = Stream::new();
+        stream.feed(b"ab");
+        assert_eq!(parse(&mut stream), Ok(Some(())));
+        assert_eq!(parse(&mut stream), Ok(None));
+        stream.feed(b"c");
+        assert_eq!(parse(&mut stream), Ok(Some(())));
+        assert_eq!(calls.get(), 2);
+        assert_eq!(stream.buffered(), b"abc");
+    }
+
+    #[test]
+    fn test_offset() {
+        let mut stream = Stream::new();
+        stream.feed(b"ab cd");
+        assert_eq!(stream.parse(word), Ok(Some(b"ab".to_vec())));
+        stream.feed(b" ");
+        assert!(stream.parse(word).is_ok());
+        stream.feed(b" x");
+        let error = stream.parse(word).unwrap_err();
+        assert_eq!(stream.offset(&error), 6);
+    }
+}
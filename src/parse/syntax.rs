@@ -0,0 +1,330 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+
+use super::str::literal;
+use super::{comb, repeat, sequence, Parse};
+
+// Syntaxes, which both parse text and print values back to text
+//
+// A syntax is built once from the combinators here, and gives a parser and a printer that agree,
+// so printed values parse back to the same value. The combinators parse like the parser
+// combinators of the same name. To print, `map` takes an inverse function, which returns `None`
+// for values the syntax doesn't describe, and `either` prints with the first alternative that can.
+
+/// A parser of text that can also print the values it parses
+pub trait Syntax<T> {
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, T>;
+
+    /// Appends the value as text, or returns false if the syntax can't describe it
+    /// Some text may have been appended when printing fails.
+    fn print(&self, value: &T, out: &mut String) -> bool;
+
+    /// The value as text, if the syntax can describe it
+    fn to_text(&self, value: &T) -> Option<String> {
+        let mut out = String::new();
+        self.print(value, &mut out).then_some(out)
+    }
+}
+
+/// Literal text, which is parsed and printed as is
+pub struct Lit(&'static str);
+
+pub fn lit(text: &'static str) -> Lit {
+    Lit(text)
+}
+
+impl Syntax<()> for Lit {
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, ()> {
+        comb::map(literal(self.0), |_| ())(i)
+    }
+
+    fn print(&self, _: &(), out: &mut String) -> bool {
+        out.push_str(self.0);
+        true
+    }
+}
+
+/// A syntax of a parser and a printer, for the parts that aren't built from other syntaxes
+pub struct Leaf<P, W> {
+    parser: P,
+    printer: W,
+}
+
+pub fn leaf<T, P, W>(parser: P, printer: W) -> Leaf<P, W>
+where
+    P: Fn(&str) -> Parse<&str, T>,
+    W: Fn(&T, &mut String) -> bool,
+{
+    Leaf { parser, printer }
+}
+
+impl<T, P, W> Syntax<T> for Leaf<P, W>
+where
+    P: Fn(&str) -> Parse<&str, T>,
+    W: Fn(&T, &mut String) -> bool,
+{
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, T> {
+        (self.parser)(i)
+    }
+
+    fn print(&self, value: &T, out: &mut String) -> bool {
+        (self.printer)(value, out)
+    }
+}
+
+/// See `map`
+pub struct Map<S, F, G, A> {
+    syntax: S,
+    to: F,
+    from: G,
+    inner: PhantomData<fn() -> A>,
+}
+
+/// Maps parsed values with `to`, and maps values back with `from` to print them
+pub fn map<A, B, S, F, G>(syntax: S, to: F, from: G) -> Map<S, F, G, A>
+where
+    S: Syntax<A>,
+    F: Fn(A) -> B,
+    G: Fn(&B) -> Option<Cow<'_, A>>,
+    A: Clone,
+{
+    Map {
+        syntax,
+        to,
+        from,
+        inner: PhantomData,
+    }
+}
+
+impl<A, B, S, F, G> Syntax<B> for Map<S, F, G, A>
+where
+    S: Syntax<A>,
+    F: Fn(A) -> B,
+    G: Fn(&B) -> Option<Cow<'_, A>>,
+    A: Clone,
+{
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, B> {
+        comb::map(|i| self.syntax.parse(i), &self.to)(i)
+    }
+
+    fn print(&self, value: &B, out: &mut String) -> bool {
+        match (self.from)(value) {
+            Some(inner) => self.syntax.print(&inner, out),
+            None => false,
+        }
+    }
+}
+
+/// See `ret`
+pub struct Ret<S, T>(S, T);
+
+/// Parses to `value`, and prints only `value`
+pub fn ret<S, T>(syntax: S, value: T) -> Ret<S, T>
+where
+    S: Syntax<()>,
+    T: Clone + PartialEq,
+{
+    Ret(syntax, value)
+}
+
+impl<S, T> Syntax<T> for Ret<S, T>
+where
+    S: Syntax<()>,
+    T: Clone + PartialEq,
+{
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, T> {
+        comb::map(|i| self.0.parse(i), |_| self.1.clone())(i)
+    }
+
+    fn print(&self, value: &T, out: &mut String) -> bool {
+        *value == self.1 && self.0.print(&(), out)
+    }
+}
+
+/// See `serial`
+pub struct Serial<S1, S2>(S1, S2);
+
+/// See `sequence::serial`
+pub fn serial<S1, S2>(s1: S1, s2: S2) -> Serial<S1, S2> {
+    Serial(s1, s2)
+}
+
+impl<A, B, S1: Syntax<A>, S2: Syntax<B>> Syntax<(A, B)> for Serial<S1, S2> {
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, (A, B)> {
+        sequence::serial(|i| self.0.parse(i), |i| self.1.parse(i))(i)
+    }
+
+    fn print(&self, (a, b): &(A, B), out: &mut String) -> bool {
+        self.0.print(a, out) && self.1.print(b, out)
+    }
+}
+
+/// See `preceded`
+pub struct Preceded<S1, S2>(S1, S2);
+
+/// See `sequence::preceded`
+pub fn preceded<S1, S2>(s1: S1, s2: S2) -> Preceded<S1, S2> {
+    Preceded(s1, s2)
+}
+
+impl<T, S1: Syntax<()>, S2: Syntax<T>> Syntax<T> for Preceded<S1, S2> {
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, T> {
+        sequence::preceded(|i| self.0.parse(i), |i| self.1.parse(i))(i)
+    }
+
+    fn print(&self, value: &T, out: &mut String) -> bool {
+        self.0.print(&(), out) && self.1.print(value, out)
+    }
+}
+
+/// See `terminated`
+pub struct Terminated<S1, S2>(S1, S2);
+
+/// See `sequence::terminated`
+pub fn terminated<S1, S2>(s1: S1, s2: S2) -> Terminated<S1, S2> {
+    Terminated(s1, s2)
+}
+
+impl<T, S1: Syntax<T>, S2: Syntax<()>> Syntax<T> for Terminated<S1, S2> {
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, T> {
+        sequence::terminated(|i| self.0.parse(i), |i| self.1.parse(i))(i)
+    }
+
+    fn print(&self, value: &T, out: &mut String) -> bool {
+        self.0.print(value, out) && self.1.print(&(), out)
+    }
+}
+
+/// See `either`
+pub struct Either<S1, S2>(S1, S2);
+
+/// See `comb::either`
+/// Prints with the first syntax if it can describe the value, and otherwise with the second.
+pub fn either<S1, S2>(s1: S1, s2: S2) -> Either<S1, S2> {
+    Either(s1, s2)
+}
+
+impl<T, S1: Syntax<T>, S2: Syntax<T>> Syntax<T> for Either<S1, S2> {
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, T> {
+        comb::either(|i| self.0.parse(i), |i| self.1.parse(i))(i)
+    }
+
+    fn print(&self, value: &T, out: &mut String) -> bool {
+        let len = out.len();
+        if self.0.print(value, out) {
+            return true;
+        }
+        out.truncate(len);
+        self.1.print(value, out)
+    }
+}
+
+/// See `optional`
+pub struct Optional<S>(S);
+
+/// See `comb::optional`
+pub fn optional<S>(syntax: S) -> Optional<S> {
+    Optional(syntax)
+}
+
+impl<T, S: Syntax<T>> Syntax<Option<T>> for Optional<S> {
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, Option<T>> {
+        comb::optional(|i| self.0.parse(i))(i)
+    }
+
+    fn print(&self, value: &Option<T>, out: &mut String) -> bool {
+        match value {
+            Some(value) => self.0.print(value, out),
+            None => true,
+        }
+    }
+}
+
+/// See `separated_items`
+pub struct SeparatedItems<S1, S2>(S1, S2);
+
+/// See `repeat::separated_items`
+pub fn separated_items<S1, S2>(separator: S1, item: S2) -> SeparatedItems<S1, S2> {
+    SeparatedItems(separator, item)
+}
+
+impl<T, S1: Syntax<()>, S2: Syntax<T>> Syntax<Vec<T>> for SeparatedItems<S1, S2> {
+    fn parse<'a>(&self, i: &'a str) -> Parse<&'a str, Vec<T>> {
+        repeat::separated_items(|i| self.0.parse(i), |i| self.1.parse(i))(i)
+    }
+
+    fn print(&self, items: &Vec<T>, out: &mut String) -> bool {
+        items
+            .iter()
+            .enumerate()
+            .all(|(index, item)| (index == 0 || self.0.print(&(), out)) && self.1.print(item, out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::str::digit;
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Shape {
+        Point,
+        Circle(u8),
+        Polygon(Vec<u8>),
+    }
+
+    fn number() -> impl Syntax<u8> {
+        leaf(
+            |i: &str| digit(i),
+            |d: &u8, out: &mut String| match char::from_digit(*d as u32, 10) {
+                Some(c) => {
+                    out.push(c);
+                    true
+                }
+                None => false,
+            },
+        )
+    }
+
+    fn shape() -> impl Syntax<Shape> {
+        either(
+            ret(lit("point"), Shape::Point),
+            either(
+                map(
+                    preceded(lit("circle "), number()),
+                    Shape::Circle,
+                    |s| match s {
+                        Shape::Circle(r) => Some(Cow::Owned(*r)),
+                        _ => None,
+                    },
+                ),
+                map(
+                    preceded(lit("polygon "), separated_items(lit(","), number())),
+                    Shape::Polygon,
+                    |s| match s {
+                        Shape::Polygon(points) => Some(Cow::Borrowed(points)),
+                        _ => None,
+                    },
+                ),
+            ),
+        )
+    }
+
+    #[test]
+    fn test_syntax() {
+        let shape = shape();
+        for text in ["point", "circle 3", "polygon 1,2,3"] {
+            let (value, _) = shape.parse(text).finish().unwrap();
+            assert_eq!(shape.to_text(&value).as_deref(), Some(text));
+        }
+        assert_eq!(
+            shape.parse("polygon 1,2;"),
+            Parse::Success(Shape::Polygon(vec![1, 2]), ";")
+        );
+        assert_eq!(shape.to_text(&Shape::Circle(12)), None);
+        assert_eq!(
+            shape.to_text(&Shape::Polygon(vec![])),
+            Some("polygon ".to_string())
+        );
+    }
+}
@@ -1,13 +1,18 @@
+use super::error::{Error, Expected};
+use super::input::Input;
 use super::Parse;
-use std::fmt::Display;
+
+/// Names the parser in errors
+/// The label is added to the error context, and replaces the expected items if nothing was consumed
 pub fn label<I, O, P>(p: P, label: &'static str) -> impl Fn(I) -> Parse<I, O>
 where
     P: Fn(I) -> Parse<I, O>,
+    I: Input,
 {
     move |i: I| match p(i) {
         s @ Parse::Success(_, _) => s,
-        Parse::Retreat(_) => Parse::Retreat(format!("Error parsing {}", label)),
-        Parse::Halt(_) => Parse::Halt(format!("Halted at parsing {}", label)),
+        Parse::Retreat(r) => Parse::Retreat(r.label(&i, label)),
+        Parse::Halt(h) => Parse::Halt(h.label(&i, label)),
         Parse::Limit(res, sur) => Parse::Limit(res, sur),
     }
 }
@@ -16,11 +21,10 @@ where
 pub fn halt<I, O, P>(p: P) -> impl Fn(I) -> Parse<I, O>
 where
     P: Fn(I) -> Parse<I, O>,
-    I: Display + Copy,
 {
     move |i: I| match p(i) {
         s @ Parse::Success(_, _) => s,
-        Parse::Retreat(r) => Parse::Halt(format!("Halted {r}, for {i}")),
+        Parse::Retreat(r) => Parse::Halt(r),
         Parse::Halt(h) => Parse::Halt(h),
         Parse::Limit(r, s) => Parse::Limit(r, s),
     }
@@ -30,6 +34,7 @@ where
 pub fn accept_limit<I, O, P>(p: P) -> impl Fn(I) -> Parse<I, O>
 where
     P: Fn(I) -> Parse<I, O>,
+    I: Input,
 {
     move |i: I| match p(i) {
         s @ Parse::Success(_, _) => s,
@@ -37,9 +42,7 @@ where
         Parse::Halt(h) => Parse::Halt(h),
         Parse::Limit(r, s) => match r {
             Some(r) => Parse::Success(r, s),
-            None => Parse::Retreat(
-                "not to use accept limit on a parser that isnt able to Some on a limit".to_string(),
-            ),
+            None => Parse::Retreat(Error::new(&s, Expected::More)),
         },
     }
 }
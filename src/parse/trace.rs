@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::fmt;
+
+use super::error::Error;
+use super::input::Input;
+use super::Parse;
+
+/// How a traced parser finished
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// Parsed up to the offset
+    Success(usize),
+    Retreat(Error),
+    Halt(Error),
+    /// Ran out of input, with or without a result
+    Limit(bool),
+}
+
+/// One call of a traced parser
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub name: &'static str,
+    /// Number of traced parsers this call is nested in
+    pub depth: usize,
+    /// Offset of the input, relative to the first traced call
+    pub offset: usize,
+    /// `None` when the parser hasn't returned, such as after a panic
+    pub outcome: Option<Outcome>,
+}
+
+/// Calls of traced parsers, in the order they were entered
+/// Displays as a tree indented by depth.
+#[derive(Debug, Default)]
+pub struct Trace {
+    calls: Vec<Call>,
+    /// Indices of the calls not yet returned
+    open: Vec<usize>,
+    /// Remaining length at the first call, which offsets are relative to
+    origin: Option<usize>,
+}
+
+thread_local! {
+    static TRACE: RefCell<Option<Trace>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with tracing enabled, returning its result and the calls of the traced parsers
+/// Tracing is compiled out of release builds, where no calls are collected.
+pub fn with_trace<T>(f: impl FnOnce() -> T) -> (T, Trace) {
+    let outer = TRACE.with(|t| t.replace(Some(Trace::default())));
+    let result = f();
+    let trace = TRACE.with(|t| t.replace(outer)).unwrap_or_default();
+    (result, trace)
+}
+
+/// Records calls of the parser as `name`, when called within `with_trace`
+/// Outside `with_trace` the parser is run as is, after checking whether tracing is enabled. In
+/// release builds the parser is always run as is, so tracing costs nothing.
+pub fn trace<I, O, P>(name: &'static str, p: P) -> impl Fn(I) -> Parse<I, O>
+where
+    P: Fn(I) -> Parse<I, O>,
+    I: Input,
+{
+    move |i: I| {
+        if !cfg!(debug_assertions) {
+            return p(i);
+        }
+        let remaining = i.input_len();
+        let tracing = TRACE.with(|t| match t.borrow_mut().as_mut() {
+            Some(t) => {
+                t.enter(name, remaining);
+                true
+            }
+            None => false,
+        });
+        let result = p(i);
+        if tracing {
+            let outcome = match &result {
+                Parse::Success(_, sur) => Outcome::Success(sur.input_len()),
+                Parse::Retreat(r) => Outcome::Retreat(r.clone()),
+                Parse::Halt(h) => Outcome::Halt(h.clone()),
+                Parse::Limit(res, _) => Outcome::Limit(res.is_some()),
+            };
+            TRACE.with(|t| {
+                if let Some(t) = t.borrow_mut().as_mut() {
+                    t.exit(outcome)
+                }
+            });
+        }
+        result
+    }
+}
+
+impl Trace {
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    fn enter(&mut self, name: &'static str, remaining: usize) {
+        let origin = *self.origin.get_or_insert(remaining);
+        self.calls.push(Call {
+            name,
+            depth: self.open.len(),
+            offset: origin.saturating_sub(remaining),
+            outcome: None,
+        });
+        self.open.push(self.calls.len() - 1);
+    }
+
+    /// Success offsets are given as remaining length, and converted here
+    fn exit(&mut self, outcome: Outcome) {
+        let origin = self.origin.unwrap_or(0);
+        if let Some(index) = self.open.pop() {
+            self.calls[index].outcome = Some(match outcome {
+                Outcome::Success(remaining) => Outcome::Success(origin.saturating_sub(remaining)),
+                outcome => outcome,
+            });
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Success(end) => write!(f, "success to {end}"),
+            Outcome::Retreat(r) => write!(f, "retreat: {r}"),
+            Outcome::Halt(h) => write!(f, "halt: {h}"),
+            Outcome::Limit(true) => write!(f, "limit with result"),
+            Outcome::Limit(false) => write!(f, "limit"),
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for call in self.calls.iter() {
+            write!(
+                f,
+                "{:indent$}{} at {}: ",
+                "",
+                call.name,
+                call.offset,
+                indent = call.depth * 2
+            )?;
+            match &call.outcome {
+                Some(outcome) => writeln!(f, "{outcome}")?,
+                None => writeln!(f, "unfinished")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::comb::either;
+    use super::super::sequence::serial;
+    use super::super::str::char;
+    use super::*;
+
+    #[test]
+    #[cfg_attr(
+        not(debug_assertions),
+        ignore = "tracing is compiled out of release builds"
+    )]
+    fn test_trace() {
+        let a = trace("a", char('a'));
+        let b = trace("b", char('b'));
+        let pair = trace("pair", serial(&a, either(&a, &b)));
+
+        // Not recorded outside of a tracing context
+        assert_eq!(pair("ab"), Parse::Success(('a', 'b'), ""));
+        let (result, trace) = with_trace(|| pair("abc"));
+        assert_eq!(result, Parse::Success(('a', 'b'), "c"));
+        assert_eq!(
+            trace.to_string(),
+            "pair at 0: success to 2\n\
+             \x20 a at 0: success to 1\n\
+             \x20 a at 1: retreat: expected 'a'\n\
+             \x20 b at 1: success to 2\n"
+        );
+
+        let (_, trace) = with_trace(|| pair("a"));
+        assert_eq!(trace.calls()[0].outcome, Some(Outcome::Limit(false)));
+        assert_eq!(trace.calls().len(), 4);
+    }
+}
@@ -1,8 +1,8 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 use crate::parse::Parse;
 
-use self::primitives::unreserved;
 pub mod primitives;
 
 type Path = Vec<String>;
@@ -26,17 +26,18 @@ struct Absolute<T>(T);
 #[derive(PartialEq)]
 struct URL {}
 
-#[derive(Debug)]
+/// Schemes are case-insensitive, and kept in lowercase
+#[derive(PartialEq, Debug)]
 struct Scheme(String);
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 struct Authority {
     user_info: Option<UserInfo>,
     host: Host,
     port: Option<Port>,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 struct Port(u16);
 
 #[derive(PartialEq, Debug)]
@@ -47,23 +48,31 @@ struct HttpUrl {
 #[derive(PartialEq, Debug)]
 struct URI {}
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 enum Host {
     Literal(IPLiteral),
     IPv4(IPv4Address),
     Name(RegistrationName),
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct UserInfo(String);
 
-#[derive(PartialEq, Debug)]
+/// User info that borrows from the input where it has no percent encoding
+#[derive(PartialEq, Debug, Clone)]
+pub struct UserInfoRef<'a>(Cow<'a, str>);
+
+#[derive(PartialEq, Debug, Clone)]
 pub struct RegistrationName(String);
 
-#[derive(PartialEq, Debug)]
+/// A registration name that borrows from the input where it has no percent encoding
+#[derive(PartialEq, Debug, Clone)]
+pub struct RegistrationNameRef<'a>(Cow<'a, str>);
+
+#[derive(PartialEq, Debug, Clone)]
 struct IPv4Address(u8, u8, u8, u8);
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 enum IPLiteral {
     IPv6Address,
     IPvFuture,
@@ -74,43 +83,97 @@ pub trait Parser {
     where
         Self: Sized;
 }
-impl PartialEq for Scheme {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.to_lowercase() == other.0.to_lowercase()
-    }
-}
 
 mod parsers {
 
-    use crate::parse::comb::{either, either3, either4, optional, ret};
-    use crate::parse::repeat::repeat_some;
-    use crate::parse::sequence::{preceded, serial, serial3, serial4, terminated};
-    use crate::parse::str::{alpha_char, char, peek_char, take_while};
+    use std::borrow::Cow;
+    use std::fmt::Write;
+
+    use crate::parse::class::CharClass;
+    use crate::parse::comb::{either, map_bool, recognize};
+    use crate::parse::error::{Error, Expected};
+    use crate::parse::memo::{memo, with_memo};
+    use crate::parse::sequence::{serial, terminated};
+    use crate::parse::str::{alpha_char, char, take_class, take_while};
+    use crate::parse::syntax::{self, leaf, lit, Syntax};
+    use crate::parse::tools::label;
     use crate::parse::{comb::map, Parse};
+    use crate::{either, serial};
 
-    use super::primitives::{self, dec_hextet, dec_octet, percent_encoded, sub_delim, unreserved};
+    use super::primitives::{
+        self, dec_hextet, dec_octet, percent_decoded, percent_encode, NAME, USER_INFO,
+    };
     use super::*;
 
     impl Parser for Authority {
         fn parse(i: &str) -> Parse<&str, Self> {
-            map(
-                serial3(
-                    optional(terminated(UserInfo::parse, char('@'))),
-                    Host::parse,
-                    optional(preceded(char(':'), Port::parse)),
+            // The user info is optional, so the host is parsed over the same text after it fails
+            with_memo(i, || authority().parse(i))
+        }
+    }
+
+    impl Authority {
+        /// The authority as text, with the characters that need it percent encoded
+        /// `None` if the host is an IP literal, which isn't supported yet.
+        pub fn to_text(&self) -> Option<String> {
+            authority().to_text(self)
+        }
+    }
+
+    /// The syntax of authorities, which both parses and prints them
+    fn authority() -> impl Syntax<Authority> {
+        let user_info = leaf(UserInfo::parse, |u: &UserInfo, out: &mut String| {
+            percent_encode(&u.0, &USER_INFO, out);
+            true
+        });
+        let port = leaf(Port::parse, |p: &Port, out: &mut String| {
+            write!(out, "{}", p.0).is_ok()
+        });
+        syntax::map(
+            syntax::serial(
+                syntax::optional(syntax::terminated(user_info, lit("@"))),
+                syntax::serial(
+                    leaf(Host::parse, print_host),
+                    syntax::optional(syntax::preceded(lit(":"), port)),
                 ),
-                |(user_info, host, port)| Authority {
-                    user_info,
-                    host,
-                    port,
-                },
-            )(i)
+            ),
+            |(user_info, (host, port))| Authority {
+                user_info,
+                host,
+                port,
+            },
+            |a| {
+                Some(Cow::Owned((
+                    a.user_info.clone(),
+                    (a.host.clone(), a.port.clone()),
+                )))
+            },
+        )
+    }
+
+    fn print_host(host: &Host, out: &mut String) -> bool {
+        match host {
+            Host::IPv4(IPv4Address(a, b, c, d)) => write!(out, "{a}.{b}.{c}.{d}").unwrap(),
+            Host::Name(RegistrationName(name)) => {
+                let start = out.len();
+                percent_encode(name, &NAME, out);
+                // A name that starts like an address is kept a name by encoding its first digit
+                let text = &out[start..];
+                if text.starts_with(|c: char| c.is_ascii_digit())
+                    && !IPv4Address::parse(text).is_retreat()
+                {
+                    let digit = out.as_bytes()[start];
+                    out.replace_range(start..start + 1, &format!("%{digit:02X}"));
+                }
+            }
+            Host::Literal(_) => return false,
         }
+        true
     }
 
     impl Parser for Port {
         fn parse(i: &str) -> Parse<&str, Self> {
-            map(dec_hextet, |r| Port(r))(i)
+            map(dec_hextet, Port)(i)
         }
     }
 
@@ -119,7 +182,7 @@ mod parsers {
             terminated(
                 either(
                     map(char('*'), |_| Self::Asterix),
-                    map(Authority::parse, |a| Self::Connect(a)),
+                    map(Authority::parse, Self::Connect),
                     //map(URI::parse, |u| Self::Absolute(URI)),
                 ),
                 primitives::url_end,
@@ -130,13 +193,13 @@ mod parsers {
     impl Parser for Scheme {
         fn parse(i: &str) -> Parse<&str, Self> {
             map(
-                serial(
-                    map(alpha_char(i), |c| c.to_string()),
-                    take_while(|c| {
+                recognize(serial(
+                    alpha_char,
+                    take_while(|c: char| {
                         c.is_ascii_alphabetic() || c.is_ascii_digit() || "+-.".contains(c)
                     }),
-                ),
-                |(f, r)| Scheme(format!("{f}{r}")),
+                )),
+                |s: &str| Scheme(s.to_ascii_lowercase()),
             )(i)
         }
     }
@@ -144,7 +207,7 @@ mod parsers {
     impl Parser for IPv4Address {
         fn parse(i: &str) -> Parse<&str, Self> {
             map(
-                serial4(
+                serial!(
                     terminated(dec_octet, char('.')),
                     terminated(dec_octet, char('.')),
                     terminated(dec_octet, char('.')),
@@ -156,41 +219,68 @@ mod parsers {
     }
     impl Parser for IPLiteral {
         fn parse(i: &str) -> Parse<&str, Self> {
-            Parse::Retreat("IPv6 parser not implemented".to_string())
+            Parse::Retreat(Error::new(&i, Expected::Label("IP literal")))
         }
     }
+    /// Percent-decoded characters of `class`, at least one
+    fn decoded<'a>(
+        class: &'static CharClass,
+        name: &'static str,
+    ) -> impl Fn(&'a str) -> Parse<&'a str, Cow<'a, str>> {
+        label(
+            map_bool(percent_decoded(take_class(class)), |s| !s.is_empty()),
+            name,
+        )
+    }
+
+    // The owned user info and names are memoized, since authorities try the user info and the
+    // host over the same text, which are mostly the same characters
+
     impl Parser for UserInfo {
         fn parse(i: &str) -> Parse<&str, Self> {
-            map(
-                repeat_some(either4(
-                    map(unreserved, |s| s.to_string()),
-                    percent_encoded,
-                    map(sub_delim, |c| c.to_string()),
-                    map(char(':'), |c| c.to_string()),
-                )),
-                |r| UserInfo(r.join("")),
+            memo(
+                "user info",
+                map(UserInfoRef::parse, UserInfoRef::into_owned),
             )(i)
         }
     }
 
+    impl<'a> UserInfoRef<'a> {
+        pub fn parse(i: &'a str) -> Parse<&'a str, Self> {
+            map(decoded(&USER_INFO, "user info"), UserInfoRef)(i)
+        }
+
+        pub fn into_owned(self) -> UserInfo {
+            UserInfo(self.0.into_owned())
+        }
+    }
+
     impl Parser for RegistrationName {
         fn parse(i: &str) -> Parse<&str, Self> {
-            map(
-                repeat_some(either3(
-                    map(unreserved, |s| s.to_string()),
-                    percent_encoded,
-                    map(sub_delim, |c| c.to_string()),
-                )),
-                |r| RegistrationName(r.join("")),
+            memo(
+                "registration name",
+                map(RegistrationNameRef::parse, RegistrationNameRef::into_owned),
             )(i)
         }
     }
+
+    impl<'a> RegistrationNameRef<'a> {
+        pub fn parse(i: &'a str) -> Parse<&'a str, Self> {
+            map(decoded(&NAME, "registration name"), RegistrationNameRef)(i)
+        }
+
+        pub fn into_owned(self) -> RegistrationName {
+            RegistrationName(self.0.into_owned())
+        }
+    }
     impl Parser for Host {
         fn parse(i: &str) -> Parse<&str, Self> {
-            either3(
-                map(IPLiteral::parse, |r| Host::Literal(r)),
-                map(IPv4Address::parse, |r| Host::IPv4(r)),
-                map(RegistrationName::parse, |r| Host::Name(r)),
+            // Names overlap addresses, so the address is memoized like the name, for grammars that
+            // try the host again
+            either!(
+                map(IPLiteral::parse, Host::Literal),
+                map(memo("ipv4 address", IPv4Address::parse), Host::IPv4),
+                map(RegistrationName::parse, Host::Name),
             )(i)
         }
     }
@@ -203,6 +293,7 @@ mod parsers {
 
     #[cfg(test)]
     mod tests {
+        use crate::parse::abnf::Grammar;
 
         // #[test]
         // fn test_relative_path() {
@@ -262,13 +353,11 @@ mod parsers {
         fn test_target_asterix() {
             assert_eq!(Target::parse("*"), Parse::Success(Target::Asterix, ""));
             assert_eq!(Target::parse("* "), Parse::Success(Target::Asterix, " "));
-            println!("is: {:?}", Target::parse("*s"));
             assert!(Target::parse("*s").is_retreat());
         }
 
         #[test]
         fn test_scheme() {
-            assert_eq!(Scheme("N".to_string()), Scheme("n".to_string()));
             assert_eq!(
                 Scheme::parse("N "),
                 Parse::Success(Scheme("n".to_string()), " ")
@@ -277,6 +366,10 @@ mod parsers {
                 Scheme::parse("N+3 "),
                 Parse::Success(Scheme("n+3".to_string()), " ")
             );
+            assert_eq!(
+                Scheme::parse("hTTp+3 "),
+                Parse::Success(Scheme("http+3".to_string()), " ")
+            );
             assert!(Scheme::parse(" N+3 ").is_retreat());
         }
 
@@ -295,6 +388,56 @@ mod parsers {
             assert!(IPv4Address::parse("340.23.3.0").is_retreat());
             assert!(IPv4Address::parse("14023.3.0").is_retreat());
         }
+        /// Rules of RFC 3986 appendix A, which the handwritten parsers are checked against
+        const RFC_3986: &str = "\
+scheme        = ALPHA *( ALPHA / DIGIT / \"+\" / \"-\" / \".\" )
+IPv4address   = dec-octet \".\" dec-octet \".\" dec-octet \".\" dec-octet
+dec-octet     = DIGIT                 ; 0-9
+              / %x31-39 DIGIT         ; 10-99
+              / \"1\" 2DIGIT            ; 100-199
+              / \"2\" %x30-34 DIGIT     ; 200-249
+              / \"25\" %x30-35          ; 250-255
+";
+
+        #[test]
+        fn test_rfc_3986() {
+            let grammar: Grammar = RFC_3986.parse().unwrap();
+            assert_eq!(grammar.undefined(), Vec::<&str>::new());
+            fn complete<O>(p: Parse<&str, O>) -> bool {
+                p.finish().is_ok_and(|(_, rest)| rest.is_empty())
+            }
+
+            for n in 0..1000 {
+                for octet in [format!("{n}"), format!("0{n}"), format!("{n:03}")] {
+                    assert_eq!(
+                        complete(dec_octet(&octet)),
+                        grammar.matches("dec-octet", &octet),
+                        "{octet}"
+                    );
+                }
+            }
+            for address in [
+                "0.0.0.0",
+                "255.255.255.255",
+                "1.2.3",
+                "1.2.3.256",
+                "01.2.3.4",
+            ] {
+                assert_eq!(
+                    complete(IPv4Address::parse(address)),
+                    grammar.matches("IPv4address", address),
+                    "{address}"
+                );
+            }
+            for scheme in ["http", "a+b-c.d", "h2", "2h", "-a", "a_b"] {
+                assert_eq!(
+                    complete(Scheme::parse(scheme)),
+                    grammar.matches("scheme", scheme),
+                    "{scheme}"
+                );
+            }
+        }
+
         #[test]
         fn test_user_info() {
             assert_eq!(
@@ -309,6 +452,18 @@ mod parsers {
                 UserInfo::parse("he:!l%20lo "),
                 Parse::Success(UserInfo("he:!l lo".to_string()), " ")
             );
+
+            // Borrowed unless it has percent encoding
+            match UserInfoRef::parse("he:!llo@") {
+                Parse::Success(UserInfoRef(Cow::Borrowed("he:!llo")), "@") => (),
+                p => panic!("{p:?}"),
+            }
+            match RegistrationNameRef::parse("a%20b/") {
+                Parse::Success(RegistrationNameRef(Cow::Owned(name)), "/") => {
+                    assert_eq!(name, "a b")
+                }
+                p => panic!("{p:?}"),
+            }
         }
         #[test]
         fn test_host() {
@@ -372,5 +527,117 @@ mod parsers {
                 )
             );
         }
+
+        #[test]
+        fn test_authority_to_text() {
+            let name = |name: &str| Host::Name(RegistrationName(name.to_string()));
+            let authority = Authority {
+                user_info: Some(UserInfo("a b%".to_string())),
+                host: name("1.2.3.4"),
+                port: Some(Port(80)),
+            };
+            let text = authority.to_text().unwrap();
+            assert_eq!(text, "a%20b%25@%31.2.3.4:80");
+            assert_eq!(
+                Authority::parse(&format!("{text}/")),
+                Parse::Success(authority, "/")
+            );
+            let authority = Authority {
+                user_info: None,
+                host: Host::IPv4(IPv4Address(1, 2, 3, 4)),
+                port: None,
+            };
+            assert_eq!(authority.to_text().as_deref(), Some("1.2.3.4"));
+            let authority = Authority {
+                user_info: None,
+                host: name("x.com"),
+                port: None,
+            };
+            assert_eq!(authority.to_text().as_deref(), Some("x.com"));
+        }
+
+        /// Authorities of RFC 3986, with the non-ASCII characters of RFC 3987, percent-encodings
+        /// of ASCII only, since the parser decodes them, and ports that fit in 16 bits
+        const AUTHORITY: &str = "\
+authority   = [ userinfo \"@\" ] host [ \":\" port ]
+userinfo    = 1*( unreserved / pct-encoded / sub-delims / \":\" )
+host        = IPv4address / reg-name
+reg-name    = 1*( unreserved / pct-encoded / sub-delims )
+port        = \"0\" / %x31-39 *3DIGIT
+unreserved  = ALPHA / DIGIT / \"-\" / \".\" / \"_\" / \"~\" / ucschar
+ucschar     = %xA0-D7FF / %xF900-FDCF / %xFDF0-FFEF
+            / %x10000-1FFFD / %x20000-2FFFD / %x30000-3FFFD
+            / %x40000-4FFFD / %x50000-5FFFD / %x60000-6FFFD
+            / %x70000-7FFFD / %x80000-8FFFD / %x90000-9FFFD
+            / %xA0000-AFFFD / %xB0000-BFFFD / %xC0000-CFFFD
+            / %xD0000-DFFFD / %xE1000-EFFFD
+pct-encoded = \"%\" %x30-37 HEXDIG
+sub-delims  = \"!\" / \"$\" / \"&\" / \"'\" / \"(\" / \")\" / \"*\" / \"+\" / \",\" / \";\" / \"=\"
+";
+
+        #[test]
+        fn test_generated() {
+            use crate::parse::generate::Generator;
+
+            let grammar: Grammar = format!("{AUTHORITY}{RFC_3986}").parse().unwrap();
+            let mut generator = Generator::new(&grammar, 3986).max_repeat(6);
+            let decode = |text: &str| {
+                let mut decoded = String::new();
+                let mut rest = text;
+                while let Some(at) = rest.find('%') {
+                    decoded.push_str(&rest[..at]);
+                    decoded.push(u8::from_str_radix(&rest[at + 1..at + 3], 16).unwrap() as char);
+                    rest = &rest[at + 3..];
+                }
+                decoded + rest
+            };
+            for _ in 0..300 {
+                let input = generator.generate("authority");
+                let tree = grammar.parser("authority")(&input).finish().unwrap().0;
+                // An authority ends where the path starts, otherwise more of it could follow
+                let url = format!("{input}/");
+                let (authority, rest) = Authority::parse(&url).finish().unwrap();
+                assert_eq!(rest, "/", "{input}");
+
+                // Printed authorities parse back to the same authority
+                let text = authority.to_text().unwrap();
+                assert_eq!(
+                    Authority::parse(&format!("{text}/")).finish(),
+                    Ok((authority.clone(), "/")),
+                    "{input}"
+                );
+                assert_eq!(
+                    authority.user_info.map(|u| u.0),
+                    tree.find("userinfo").map(|u| decode(u.text())),
+                    "{input}"
+                );
+                assert_eq!(
+                    authority.port.map(|p| p.0),
+                    tree.find("port").map(|p| p.text().parse().unwrap()),
+                    "{input}"
+                );
+                match authority.host {
+                    Host::IPv4(IPv4Address(a, b, c, d)) => {
+                        assert_eq!(
+                            Some(format!("{a}.{b}.{c}.{d}")),
+                            tree.find("IPv4address").map(|h| h.text().to_string()),
+                            "{input}"
+                        )
+                    }
+                    Host::Name(name) => assert_eq!(
+                        Some(name.0),
+                        tree.find("reg-name").map(|h| decode(h.text())),
+                        "{input}"
+                    ),
+                    Host::Literal(_) => panic!("{input}"),
+                }
+
+                // Invalid inputs must not panic, and what is parsed must be valid
+                let mutated = generator.mutate(&input);
+                if let Ok((_, "/")) = Authority::parse(&format!("{mutated}/")).finish() {
+                    assert!(grammar.matches("authority", &mutated), "{mutated}");
+                }
+            }
+        }
     }
 }
@@ -1,93 +1,123 @@
-use std::ops::Range;
+use std::borrow::Cow;
+use std::ops::RangeInclusive;
 
 use crate::parse::{
-    comb::{
-        either, either3, either5, either_of, map, map_bool, map_option, map_result,
-        map_result_halts, ret,
-    },
-    repeat::{repeat_any, repeat_some},
-    sequence::{preceded, serial, serial3},
-    str::{char, digit, peek_char, pop, take, take_some_while, take_while},
-    tools::{accept_limit, halt},
+    class::CharClass,
+    comb::{either, map, map_bool, map_result, map_result_halts, peek, ret},
+    error::{Error, Expected},
+    escape::escaped_transform,
+    repeat::repeat_any,
+    sequence::{preceded, serial},
+    str::{char, class_char, digit, eof, take, take_class},
+    tools::{accept_limit, halt, label},
     Parse,
 };
+use crate::{either, serial};
 
 #[allow(dead_code)]
 pub mod ascii_charsets {
-    pub const NUMERIC: &'static str = "0123456789";
-    pub const ALPHA_SMALL: &'static str = "abcdefghijklmnopqrstuvwxyz";
-    pub const ALPHA_CAPITAL: &'static str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
-    pub const CONTROL: &'static str = "\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\
-                                      \x0A\x0B\x0C\x0D\x0E\x0F\x10\x11\x12\x13\
-                                      \x14\x15\x16\x17\x18\x19\x1A\x1B\x1C\x1D\
-                                      \x1d\x1E\x1F";
+    use crate::parse::class::CharClass;
 
-    pub const URL_UNRESERVED: &'static str = "-._~";
-    pub const GEN_DELIMS: &'static str = ":/?#[]@";
-    pub const SUB_DELIMS: &'static str = "!$&'()*+,;=";
+    pub const NUMERIC: CharClass = CharClass::range('0', '9');
+    pub const ALPHA_SMALL: CharClass = CharClass::range('a', 'z');
+    pub const ALPHA_CAPITAL: CharClass = CharClass::range('A', 'Z');
+    pub const CONTROL: CharClass = CharClass::range('\x00', '\x1F');
+
+    pub const URL_UNRESERVED: CharClass = CharClass::ascii("-._~");
+    pub const GEN_DELIMS: CharClass = CharClass::ascii(":/?#[]@");
+    pub const SUB_DELIMS: CharClass = CharClass::ascii("!$&'()*+,;=").named("sub-delim");
     pub const PERCENT: char = '%';
-    pub const URL_ILLEGAL: &'static str = " \"<>\\^`}{|";
+    pub const URL_ILLEGAL: CharClass = CharClass::ascii(" \"<>\\^`}{|");
 }
 
+/// Non-ASCII characters allowed in IRIs, `ucschar` of RFC 3987
+pub const UCSCHAR: CharClass = CharClass::range('\u{A0}', '\u{D7FF}')
+    .union(CharClass::range('\u{F900}', '\u{FDCF}'))
+    .union(CharClass::range('\u{FDF0}', '\u{FFEF}'))
+    .union(CharClass::range('\u{10000}', '\u{1FFFD}'))
+    .union(CharClass::range('\u{20000}', '\u{2FFFD}'))
+    .union(CharClass::range('\u{30000}', '\u{3FFFD}'))
+    .union(CharClass::range('\u{40000}', '\u{4FFFD}'))
+    .union(CharClass::range('\u{50000}', '\u{5FFFD}'))
+    .union(CharClass::range('\u{60000}', '\u{6FFFD}'))
+    .union(CharClass::range('\u{70000}', '\u{7FFFD}'))
+    .union(CharClass::range('\u{80000}', '\u{8FFFD}'))
+    .union(CharClass::range('\u{90000}', '\u{9FFFD}'))
+    .union(CharClass::range('\u{A0000}', '\u{AFFFD}'))
+    .union(CharClass::range('\u{B0000}', '\u{BFFFD}'))
+    .union(CharClass::range('\u{C0000}', '\u{CFFFD}'))
+    .union(CharClass::range('\u{D0000}', '\u{DFFFD}'))
+    .union(CharClass::range('\u{E1000}', '\u{EFFFD}'));
+
+const ALPHANUMERIC: CharClass = ascii_charsets::NUMERIC
+    .union(ascii_charsets::ALPHA_SMALL)
+    .union(ascii_charsets::ALPHA_CAPITAL);
+
+/// Characters that don't need to be percent encoded
+pub const UNRESERVED: CharClass = ALPHANUMERIC
+    .union(ascii_charsets::URL_UNRESERVED)
+    .union(UCSCHAR)
+    .named("unreserved character");
+
+/// Characters of registration names that don't need to be percent encoded
+pub const NAME: CharClass = UNRESERVED
+    .union(ascii_charsets::SUB_DELIMS)
+    .named("name character");
+
+/// Characters of user info that don't need to be percent encoded
+pub const USER_INFO: CharClass = NAME
+    .union(CharClass::ascii(":"))
+    .named("user info character");
+
+/// ASCII characters that can't be part of a path segment or host name without encoding
+pub const URL_TERMINATIVE: CharClass =
+    CharClass::range('\x00', '\x7F').difference(ALPHANUMERIC.union(ascii_charsets::URL_UNRESERVED));
+
 pub fn sub_delim(i: &str) -> Parse<&str, char> {
-    map_bool(pop, |c| ascii_charsets::SUB_DELIMS.contains(*c))(i)
+    class_char(&ascii_charsets::SUB_DELIMS)(i)
 }
 
 pub fn is_ucschar(c: char) -> bool {
-    for g in [
-        0xA0..0xD7FF,
-        0xF900..0xFDCF,
-        0xFDF0..0xFFEF,
-        0x10000..0x1FFFD,
-        0x20000..0x2FFFD,
-        0x30000..0x3FFFD,
-        0x40000..0x4FFFD,
-        0x50000..0x5FFFD,
-        0x60000..0x6FFFD,
-        0x70000..0x7FFFD,
-        0x80000..0x8FFFD,
-        0x90000..0x9FFFD,
-        0xA0000..0xAFFFD,
-        0xB0000..0xBFFFD,
-        0xC0000..0xCFFFD,
-        0xD0000..0xDFFFD,
-        0xE1000..0xEFFFD,
-    ] {
-        if g.contains(&(c as u32)) {
-            return true;
-        }
-    }
-    false
+    UCSCHAR.contains(c)
 }
 
 pub fn unreserved(i: &str) -> Parse<&str, &str> {
-    take_some_while(|c| c.is_ascii_alphanumeric() || is_ucschar(c) || "-._~".contains(c))(i)
+    take_class(&UNRESERVED)(i)
 }
 
 // Parses exactly one digit within the given range
-fn digit_within(range: Range<u8>) -> impl Fn(&str) -> Parse<&str, u8> {
-    move |i: &str| map_bool(digit, |&d| d >= range.start && d <= range.end)(i)
+fn digit_within(range: RangeInclusive<u8>) -> impl Fn(&str) -> Parse<&str, u8> {
+    move |i: &str| map_bool(digit, |d| range.contains(d))(i)
 }
 
 /// Parse number between 0 and 255, with no leading 0 allowed
 pub fn dec_octet(input: &str) -> Parse<&str, u8> {
-    either5(
+    either!(
         map(
-            serial3(ret(char('2'), 200), ret(char('5'), 50), digit_within(0..5)),
+            serial!(ret(char('2'), 200), ret(char('5'), 50), digit_within(0..=5)),
             |(h, t, b)| h + t + b,
         ),
         map(
-            serial3(ret(char('2'), 200), digit_within(0..4), digit_within(0..9)),
+            serial!(
+                ret(char('2'), 200),
+                digit_within(0..=4),
+                digit_within(0..=9)
+            ),
             |(h, a, b)| h + (a * 10) + b,
         ),
         map(
-            serial3(ret(char('1'), 100), digit_within(0..9), digit_within(0..9)),
+            serial!(
+                ret(char('1'), 100),
+                digit_within(0..=9),
+                digit_within(0..=9)
+            ),
             |(h, a, b)| h + (a * 10) + b,
         ),
-        map(serial(digit_within(1..9), digit_within(0..9)), |(a, b)| {
-            (a * 10) + b
-        }),
-        map(digit_within(0..9), |a| a),
+        map(
+            serial(digit_within(1..=9), digit_within(0..=9)),
+            |(a, b)| { (a * 10) + b }
+        ),
+        map(digit_within(0..=9), |a| a),
     )(input)
 }
 
@@ -119,10 +149,10 @@ pub fn dec_hextet(input: &str) -> Parse<&str, u16> {
                 Parse::Limit(Some(result), &input[last_i + d.len_utf8()..])
             }
         } else {
-            Parse::Retreat("No digit found".to_string())
+            Parse::Retreat(Error::new(&input, Expected::Label("digit")))
         }
     } else {
-        Parse::Retreat("No digit found".to_string())
+        Parse::Retreat(Error::new(&input, Expected::Label("digit")))
     }
 }
 
@@ -132,44 +162,60 @@ pub fn dec_hextet(input: &str) -> Parse<&str, u16> {
 /// `SUB_DELIMS`: !$&'()*+,;=
 /// `URL_ILLEGAL`: "< >\\^`}{|
 pub fn is_url_terminative(c: char) -> bool {
-    let c = c as u32;
-    c < 128
-        && c > 47
-        && ((c > 57 && c < 65) || c == 96 || (c > 90 && c < 95) || c > 122 && c != 126)
-        || c < 45
-        || c == 47
+    URL_TERMINATIVE.contains(c)
 }
 
 pub fn url_end(i: &str) -> Parse<&str, ()> {
-    match peek_char(i) {
-        Parse::Success(c, _) => match is_url_terminative(c) {
-            true => Parse::Success((), i),
-            false => Parse::Retreat("is not url terminative".to_string()),
-        },
-        Parse::Limit(_, _) => Parse::Success((), i),
-        Parse::Retreat(r) => Parse::Retreat(r),
-        Parse::Halt(h) => Parse::Halt(h),
-    }
+    label(
+        either(
+            accept_limit(eof),
+            peek(map(class_char(&URL_TERMINATIVE), |_| ())),
+        ),
+        "end of URL",
+    )(i)
 }
 
 // pub fn hex_digit()
 
-/// Parses a series of percent encoded bytes to unicode string
-pub fn percent_encoded(i: &str) -> Parse<&str, String> {
+/// Parses text of `normal` characters and percent encoded bytes, and decodes it
+/// The text is only copied if it has percent encoded bytes.
+pub fn percent_decoded<'a, O, N>(normal: N) -> impl Fn(&'a str) -> Parse<&'a str, Cow<'a, str>>
+where
+    N: Fn(&'a str) -> Parse<&'a str, O>,
+{
+    escaped_transform(normal, '%', percent_encoded)
+}
+
+/// Appends the text, percent encoding the characters that aren't `allowed`
+pub fn percent_encode(text: &str, allowed: &CharClass, out: &mut String) {
+    for c in text.chars() {
+        if allowed.contains(c) {
+            out.push(c);
+            continue;
+        }
+        for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+}
+
+/// Parses a series of percent encoded bytes to unicode string, after the first `%`
+/// The bytes are decoded together, since a character can take several.
+fn percent_encoded(i: &str) -> Parse<&str, String> {
     map_result_halts(
-        repeat_some(preceded(
-            char('%'),
-            halt(map_result(
-                take(2),
-                |h| u8::from_str_radix(h, 16),
-                "invalid hex digits",
-            )),
-        )),
-        |s| -> Result<String, std::str::Utf8Error> { Ok(std::str::from_utf8(&s)?.to_string()) },
-        "unvalid unicode sequence",
+        serial(hex_byte, repeat_any(preceded(char('%'), halt(hex_byte)))),
+        |(first, mut bytes)| {
+            bytes.insert(0, first);
+            String::from_utf8(bytes)
+        },
+        "UTF-8 sequence",
     )(i)
 }
 
+fn hex_byte(i: &str) -> Parse<&str, u8> {
+    map_result(take(2), |h| u8::from_str_radix(h, 16), "hex digits")(i)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -178,14 +224,21 @@ mod tests {
         assert_eq!(unreserved("hello"), Parse::Limit(Some("hello"), ""));
         assert_eq!(unreserved("helloå˚˚~"), Parse::Limit(Some("helloå˚˚~"), ""));
         assert_eq!(unreserved("hello/s"), Parse::Success("hello", "/s"));
+        assert_eq!(
+            unreserved("aé~\u{D7FF}%"),
+            Parse::Success("aé~\u{D7FF}", "%")
+        );
+        assert!(unreserved("\u{FFFE}").is_retreat());
+        assert!(is_ucschar('\u{E1000}') && !is_ucschar('\u{E0FFF}'));
     }
     #[test]
     fn test_num_within() {
-        assert_eq!(digit_within(2..5)("45"), Parse::Success(4, "5"));
-        assert_eq!(digit_within(2..5)("4"), Parse::Success(4, ""));
-        assert_eq!(digit_within(0..1)("0"), Parse::Success(0, ""));
-        assert!(digit_within(2..5)("13").is_retreat());
-        assert!(digit_within(0..5)("6").is_retreat());
+        assert_eq!(digit_within(2..=5)("45"), Parse::Success(4, "5"));
+        assert_eq!(digit_within(2..=5)("4"), Parse::Success(4, ""));
+        assert_eq!(digit_within(0..=1)("0"), Parse::Success(0, ""));
+        assert!(digit_within(2..=5)("13").is_retreat());
+        assert!(digit_within(0..=5)("6").is_retreat());
+        assert_eq!(digit_within(0..=5)("5"), Parse::Success(5, ""));
     }
     #[test]
     fn test_dec_octet() {
@@ -216,41 +269,52 @@ mod tests {
         assert_eq!(dec_hextet("65536"), Parse::Success(6553, "6"));
     }
     #[test]
-    fn test_percent_encoded() {
-        assert_eq!(
-            percent_encoded("%20 "),
-            Parse::Success(" ".to_string(), " ")
-        );
-        assert_eq!(
-            percent_encoded("%C2%A8 "),
-            Parse::Success("¨".to_string(), " ")
-        );
-        assert_eq!(
-            percent_encoded("%C2%A8"),
-            Parse::Limit(Some("¨".to_string()), "")
-        );
-        assert!(percent_encoded("%C2 ").is_halt());
-        assert!(percent_encoded("%C2r").is_halt());
-        assert!(percent_encoded("%C2").is_limit());
-        assert!(percent_encoded("rsf").is_retreat());
-        assert!(percent_encoded(":lo").is_retreat());
-        assert!(percent_encoded(":").is_retreat());
+    fn test_percent_decoded() {
+        let decoded = percent_decoded(unreserved);
+        assert_eq!(decoded("%20 "), Parse::Success(Cow::from(" "), " "));
+        assert_eq!(decoded("a%C2%A8b "), Parse::Success(Cow::from("a¨b"), " "));
+        assert_eq!(decoded("%C2%A8"), Parse::Limit(Some(Cow::from("¨")), ""));
+        assert!(decoded("%C2 ").is_halt());
+        assert!(decoded("%C2r").is_halt());
+        assert!(decoded("%C2").is_limit());
+        assert!(matches!(
+            decoded("rsf "),
+            Parse::Success(Cow::Borrowed("rsf"), " ")
+        ));
+        assert_eq!(decoded(":lo"), Parse::Success(Cow::from(""), ":lo"));
 
         let input1 = "%C2%A8%C3%92%C2%A8%C3%94%E2%80%A1%EF%AC%82%E2%80%BA%EF%AC%81%C2%B0%C2%B0%EF%AC%81%EF%AC%81%E2%88%8F%CB%9D%CB%87%C3%8E%C3%8E%C3%93 ";
-        let expected1 = "¨Ò¨Ô‡ﬂ›ﬁ°°ﬁﬁ∏˝ˇÎÎÓ".to_string();
-        assert_eq!(percent_encoded(input1), Parse::Success(expected1, " "));
+        let expected1 = "¨Ò¨Ô‡ﬂ›ﬁ°°ﬁﬁ∏˝ˇÎÎÓ";
+        assert_eq!(decoded(input1), Parse::Success(Cow::from(expected1), " "));
 
         let input2 = "%20%20%CB%9A ";
-        let expected2 = "  ˚".to_string();
-        assert_eq!(percent_encoded(input2), Parse::Success(expected2, " "));
+        let expected2 = "  ˚";
+        assert_eq!(decoded(input2), Parse::Success(Cow::from(expected2), " "));
+    }
+    #[test]
+    fn test_percent_decoded_errors() {
+        let input = "%C2%zz";
+        match percent_decoded(unreserved)(input) {
+            Parse::Halt(e) => {
+                assert_eq!(e.offset(&input), 4);
+                assert_eq!(e.expected(), &[Expected::Label("hex digits")]);
+            }
+            p => panic!("{p:?}"),
+        }
+    }
+    #[test]
+    fn test_url_end() {
+        assert_eq!(url_end(""), Parse::Success((), ""));
+        assert_eq!(url_end(" HTTP/1.1"), Parse::Success((), " HTTP/1.1"));
+        match url_end("a") {
+            Parse::Retreat(e) => assert_eq!(e.expected(), &[Expected::Label("end of URL")]),
+            p => panic!("{p:?}"),
+        }
     }
     #[test]
     fn test_sub_delim() {
-        println!("1");
         assert!(sub_delim("f+").is_retreat());
-        println!("2");
         assert_eq!(sub_delim("+"), Parse::Success('+', ""));
-        println!("3");
         assert_eq!(sub_delim("!fm"), Parse::Success('!', "fm"));
     }
 }